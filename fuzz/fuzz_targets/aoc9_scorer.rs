@@ -0,0 +1,33 @@
+//! Feed arbitrary bytes to the Day 9 scorers. None of them should panic, and
+//! they should all agree with each other however the input is split up.
+//!
+//! Run with `cargo fuzz run aoc9_scorer` from the top of the repository.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// The days are binaries, so we pull the code in directly
+#[path = "../../src/bin/aoc9/main.rs"]
+#[allow(dead_code)]
+mod aoc9;
+
+fuzz_target!(|data: &[u8]| {
+    let whole = aoc9::scan(data);
+
+    let mut p = aoc9::stream::StreamParser::new();
+    let mid = data.len() / 2;
+    p.feed(&data[..mid]);
+    p.feed(&data[mid..]);
+    assert_eq!(whole, p.finish());
+
+    assert_eq!(whole, aoc9::parallel::scan(data, 4));
+
+    // The tree skips whole characters after a `!` where the scorers skip a
+    // byte, so only compare them on ASCII.
+    if data.is_ascii() {
+        if let Ok(tree) = aoc9::ast::parse(std::str::from_utf8(data).unwrap()) {
+            assert!(whole.errors.is_empty());
+            assert_eq!((whole.score, whole.noncancelled), (tree.score(), tree.noncancelled()));
+        }
+    }
+});
@@ -1,6 +1,34 @@
-use std::io::Read;
+use std::io::{Read, Write};
+use std::fs::File;
+use std::time::Duration;
+
+/// A single step of the reallocation. The first entry in the history has no
+/// source as it's the initial configuration we were given.
+#[derive(Debug)]
+struct Step {
+    /// The bank whose blocks were redistributed to get to this configuration
+    source: Option<usize>,
+    /// The configuration after redistributing
+    banks: Vec<u64>,
+}
 
 fn main() {
+    let mut csv_path = None;
+    let mut animate = false;
+    let mut delay = 100u64;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--csv" => csv_path = Some(args.next().expect("path for --csv")),
+            "--animate" => animate = true,
+            "--delay" => {
+                delay = args.next().expect("delay in ms").parse().expect("delay in ms");
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+
     let mut stdin = std::io::stdin();
 
     let mut input = String::new();
@@ -10,13 +38,16 @@ fn main() {
         .map(|n| u64::from_str_radix(n, 10).expect("a number"))
         .collect::<Vec<u64>>();
 
+    let history = history(&initial_banks);
+
+    // The loop detection works on its own list which gets reset
     let mut cycles: Vec<Vec<u64>> = Vec::new();
     cycles.push(initial_banks);
 
     let mut seen_once = false;
     let mut count = 0usize;
     loop {
-        let next = next_cycle(&cycles[cycles.len()-1]);
+        let (next, _) = next_cycle(&cycles[cycles.len()-1]);
         count += 1;
 
         if cycles.iter().find(|&v| next == *v).is_some() {
@@ -37,10 +68,89 @@ fn main() {
     }
 
     println!("Cycle length {}", count);
+
+    if let Some(path) = csv_path {
+        let mut f = File::create(&path).expect("create csv file");
+        write_csv(&mut f, &history).expect("write csv");
+    }
+
+    if animate {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        for (i, step) in history.iter().enumerate() {
+            write!(out, "\x1b[2J\x1b[H").expect("clear screen");
+            writeln!(out, "cycle {}", i).expect("write frame");
+            write!(out, "{}", render_frame(step)).expect("write frame");
+            out.flush().expect("flush");
+            std::thread::sleep(Duration::from_millis(delay));
+        }
+    }
 }
 
-/// Generate the next configuration of memory banks
-fn next_cycle(pc: &[u64]) -> Vec<u64> {
+/// Every configuration from the initial one up to and including the first one
+/// we've seen before, so we can export them afterwards.
+fn history(initial_banks: &[u64]) -> Vec<Step> {
+    let mut history = vec![Step { source: None, banks: initial_banks.to_vec() }];
+    loop {
+        let (next, source) = next_cycle(&history[history.len()-1].banks);
+        let repeat = history.iter().any(|s| s.banks == next);
+        history.push(Step { source: Some(source), banks: next });
+        if repeat {
+            return history;
+        }
+    }
+}
+
+/// Write out the history as CSV, one row per cycle with the index of the bank
+/// that was redistributed followed by the amount in each bank.
+fn write_csv<W: Write>(w: &mut W, history: &[Step]) -> std::io::Result<()> {
+    let nbanks = history.first().map_or(0, |s| s.banks.len());
+
+    write!(w, "cycle,source")?;
+    for i in 0..nbanks {
+        write!(w, ",bank{}", i)?;
+    }
+    writeln!(w)?;
+
+    for (i, step) in history.iter().enumerate() {
+        write!(w, "{},", i)?;
+        if let Some(source) = step.source {
+            write!(w, "{}", source)?;
+        }
+        for v in &step.banks {
+            write!(w, ",{}", v)?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/// Width in characters of the longest bar in the animation
+const BAR_WIDTH: u64 = 60;
+
+/// Render a configuration as a horizontal bar chart, with the bank which was
+/// just redistributed highlighted.
+fn render_frame(step: &Step) -> String {
+    let max_v = step.banks.iter().cloned().max().unwrap_or(0).max(1);
+
+    let mut s = String::new();
+    for (i, &v) in step.banks.iter().enumerate() {
+        let len = (v * BAR_WIDTH).div_ceil(max_v);
+        let bar = "#".repeat(len as usize);
+        if step.source == Some(i) {
+            s.push_str(&format!("{:3} {:4} \x1b[31m{}\x1b[0m\n", i, v, bar));
+        } else {
+            s.push_str(&format!("{:3} {:4} {}\n", i, v, bar));
+        }
+    }
+
+    s
+}
+
+/// Generate the next configuration of memory banks, returning it together with
+/// the position of the bank we redistributed.
+fn next_cycle(pc: &[u64]) -> (Vec<u64>, usize) {
     let mut c = Vec::new();
     for &v in pc {
         c.push(v);
@@ -58,7 +168,7 @@ fn next_cycle(pc: &[u64]) -> Vec<u64> {
         i += 1;
     }
 
-    return c;
+    (c, source)
 }
 
 /// Return the position of the most used memory bank
@@ -75,3 +185,20 @@ fn most_used(c: &[u64]) -> usize {
 
     return p;
 }
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn sample_csv() {
+        let mut out = Vec::new();
+        super::write_csv(&mut out, &super::history(&[0, 2, 7, 0])).unwrap();
+        assert_eq!("cycle,source,bank0,bank1,bank2,bank3\n\
+                    0,,0,2,7,0\n\
+                    1,2,2,4,1,2\n\
+                    2,1,3,1,2,3\n\
+                    3,0,0,2,3,4\n\
+                    4,3,1,3,4,1\n\
+                    5,2,2,4,1,2\n",
+                   String::from_utf8(out).unwrap());
+    }
+}
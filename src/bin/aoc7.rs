@@ -1,172 +1,1467 @@
+#[cfg(test)]
+extern crate aoc2017;
 extern crate regex;
 
 use regex::Regex;
-use std::collections::{HashSet, HashMap};
-use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, Write};
 
 #[derive(Debug)]
 struct Process {
     name: String,
     weight: u64,
-    above: HashSet<String>,
-    // So we can modify the process while the overall hashtable is borrowed. It
-    // might not be the best option.
-    below: RefCell<Option<String>>,
+    above: BTreeSet<String>,
+    below: Option<String>,
 }
 
 type ProcMap = HashMap<String, Process>;
 
-fn main() {
-    let procs = read_procs(std::io::stdin());
-
-    // Now that we have the processes and have pointers between them and we
-    // assume that all processes are in the one tree, we can find the one at the
-    // bottom by starting from any of them, and following the "below" pointers
-    // until there are no more.
-    //
-    // Or as an alternative, we can go through the list until we find an empty
-    // "below".
-    let bottom: &str = (|| {
-        for p in procs.values() {
-            if p.below.borrow().is_none() {
-                return &p.name;
-            }
-        }
-        unreachable!();
-    })();
-
-    println!("The bottom process is {}", bottom);
-
-    // Now we're tasked to find a single process with the wrong weight to be
-    // balanced. We start from the bottom process and see whether all of the
-    // ones above are balanced. If not, we go into the different one and see
-    // whether the ones above it are unbalanced. If they are balanced, we know
-    // it's that base process the one that's unbalanced.
-    let mut pn = bottom;
-    let mut last_common_weight = 0;
-    loop {
-        println!("Looking at {}", pn);
-        println!("Combined weight {}", combined_weight(pn, &procs));
-
-        let above = &procs.get(pn).expect("proc").above;
-        match odd_weight(above, &procs) {
-            Some((odd_one, w)) => {
-                println!("Odd one out {}", odd_one);
-                pn = odd_one;
-                last_common_weight = w;
+/// The combined weight of each program together with the ones above it
+type Weights = HashMap<String, u64>;
+
+/// The ways in which the input can fail to describe a single tower.
+#[derive(Debug, PartialEq)]
+enum TowerError {
+    /// The line with this number, counting from 1, isn't a program
+    Malformed { line: usize, text: String },
+    /// There were no programs at all
+    Empty,
+    /// More than one program has this name
+    DuplicateName(String),
+    /// `parent` claims to hold `child` but there is no such program
+    DanglingReference { parent: String, child: String },
+    /// The program is held up by more than one other program
+    MultipleParents { child: String, parents: Vec<String> },
+    /// There is more than one program at the bottom
+    MultipleRoots(Vec<String>),
+    /// These programs end up holding themselves up
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for TowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            TowerError::Malformed { line, ref text } => write!(f, "line {}: cannot parse {:?}", line, text),
+            TowerError::Empty => write!(f, "there are no programs"),
+            TowerError::DuplicateName(ref name) => write!(f, "{} appears more than once", name),
+            TowerError::DanglingReference { ref parent, ref child } => {
+                write!(f, "{} holds {} which does not exist", parent, child)
+            }
+            TowerError::MultipleParents { ref child, ref parents } => {
+                write!(f, "{} is held by {}", child, parents.join(", "))
+            }
+            TowerError::MultipleRoots(ref roots) => {
+                write!(f, "there are multiple bottom programs: {}", roots.join(", "))
+            }
+            TowerError::Cycle(ref names) => write!(f, "cycle through {}", names.join(" -> ")),
+        }
+    }
+}
+
+/// A set of processes which we know forms a single tree.
+#[derive(Debug)]
+struct Tower {
+    procs: ProcMap,
+    root: String,
+}
+
+/// A change to a program's weight
+#[derive(Debug, PartialEq)]
+struct Correction {
+    name: String,
+    weight: u64,
+    new_weight: u64,
+}
+
+/// The result of looking for the program with the wrong weight
+#[derive(Debug, PartialEq)]
+enum Balance {
+    /// Every disc is already balanced
+    Balanced,
+    /// Changing this program's weight balances the tower
+    Fix(Correction),
+    /// The disc on this program holds two programs with different weights,
+    /// and changing either of them would balance it
+    Ambiguous { disc: String, candidates: Vec<Correction> },
+    /// The disc on this program needs more than one change to balance
+    Unfixable(String),
+}
+
+impl Tower {
+    /// Build the tower out of the given processes, filling in the "below"
+    /// pointers. Every problem with the input is reported rather than just the
+    /// first one we come across.
+    fn from_procs(list: Vec<Process>) -> Result<Tower, Vec<TowerError>> {
+        let mut errors = Vec::new();
+
+        let mut procs = ProcMap::new();
+        for p in list {
+            if procs.contains_key(&p.name) {
+                errors.push(TowerError::DuplicateName(p.name));
                 continue;
             }
-            None => {
-                println!("Above is balanced");
-                break;
+            procs.insert(p.name.clone(), p);
+        }
+
+        if procs.is_empty() && errors.is_empty() {
+            return Err(vec![TowerError::Empty]);
+        }
+
+        // Sort so that the errors come out in the same order every time
+        let mut names = procs.keys().cloned().collect::<Vec<String>>();
+        names.sort();
+
+        let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+        for name in &names {
+            for child in procs[name].above.iter().cloned() {
+                if procs.contains_key(&child) {
+                    parents.entry(child).or_default().push(name.clone());
+                } else {
+                    errors.push(TowerError::DanglingReference { parent: name.clone(), child });
+                }
+            }
+        }
+
+        for name in &names {
+            match parents.get(name) {
+                Some(ps) if ps.len() > 1 => {
+                    errors.push(TowerError::MultipleParents { child: name.clone(), parents: ps.clone() });
+                }
+                Some(ps) => {
+                    procs.get_mut(name).expect("proc").below = Some(ps[0].clone());
+                }
+                None => {}
+            }
+        }
+
+        for cycle in find_cycles(&names, &procs) {
+            errors.push(TowerError::Cycle(cycle));
+        }
+
+        let roots = names.iter().filter(|&n| !parents.contains_key(n)).cloned().collect::<Vec<String>>();
+        if roots.len() > 1 {
+            errors.push(TowerError::MultipleRoots(roots.clone()));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // With no errors, there must be exactly one root, as a tower with no
+        // program at the bottom would have had a cycle.
+        Ok(Tower {
+            procs,
+            root: roots[0].clone(),
+        })
+    }
+
+    /// The programs in post-order, so every program comes after all of the
+    /// ones above it.
+    fn post_order(&self) -> Vec<&str> {
+        let mut order = Vec::with_capacity(self.procs.len());
+        // Each entry is a program and whether we've already pushed the ones
+        // above it. Going through a stack rather than recursing lets us deal
+        // with arbitrarily tall towers.
+        let mut stack = vec![(self.root.as_str(), false)];
+        while let Some((name, expanded)) = stack.pop() {
+            if expanded {
+                order.push(name);
+                continue;
+            }
+
+            stack.push((name, true));
+            for child in self.procs[name].above.iter().rev() {
+                stack.push((child, false));
+            }
+        }
+
+        order
+    }
+
+    /// Returns the combined weight of every program together with the ones
+    /// above it. We go through the tower once, from the top down to the bottom.
+    fn subtree_weights(&self) -> Weights {
+        let mut weights = Weights::with_capacity(self.procs.len());
+        for name in self.post_order() {
+            let p = &self.procs[name];
+            let w = p.weight + p.above.iter().map(|c| weights[c.as_str()]).sum::<u64>();
+            weights.insert(name.to_owned(), w);
+        }
+
+        weights
+    }
+
+    /// Find the single program whose weight is wrong. We go up from the top of
+    /// the tower and stop at the first disc which is unbalanced, as that's the
+    /// one which holds the wrong program; the ones below it are only
+    /// unbalanced because of it.
+    ///
+    /// A program on that disc is a candidate if all the others weigh the same,
+    /// but with only two programs both of them are. Changing either one changes
+    /// the weight of the disc by a different amount though, so we keep the
+    /// candidates that also leave the discs further down balanced. Only if
+    /// there's nothing below to tell them apart do we return both.
+    ///
+    /// A single change only moves the weights of the discs below it, so if
+    /// there's an unbalanced disc anywhere else, no candidate can fix it.
+    fn find_imbalance(&self, weights: &Weights) -> Balance {
+        let unbalanced = self.post_order()
+            .into_iter()
+            .filter(|&name| {
+                let above = &self.procs[name].above;
+                let ws = above.iter().map(|c| weights[c.as_str()]).collect::<Vec<u64>>();
+                // This also covers a disc holding a single program, which is
+                // always balanced.
+                !ws.iter().all(|&w| w == ws[0])
+            })
+            .collect::<Vec<&str>>();
+        let name = match unbalanced.first() {
+            Some(&name) => name,
+            None => return Balance::Balanced,
+        };
+
+        let mut below = HashSet::new();
+        let mut pn = name;
+        below.insert(pn);
+        while let Some(ref b) = self.procs[pn].below {
+            pn = b;
+            below.insert(pn);
+        }
+        if unbalanced.iter().any(|n| !below.contains(n)) {
+            return Balance::Unfixable(name.to_owned());
+        }
+
+        let above = &self.procs[name].above;
+        let ws = above.iter().map(|c| weights[c.as_str()]).collect::<Vec<u64>>();
+        let mut candidates = Vec::new();
+        for (i, child) in above.iter().enumerate() {
+            let mut others = ws.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, &w)| w);
+            let target = others.next().expect("another program");
+            if !others.all(|w| w == target) {
+                continue;
+            }
+
+            let delta = target as i64 - ws[i] as i64;
+            if !self.balanced_below(name, delta, weights) {
+                continue;
+            }
+            if let Some(c) = self.absorb(child, delta) {
+                candidates.push(c);
+            }
+        }
+
+        match candidates.len() {
+            0 => Balance::Unfixable(name.to_owned()),
+            1 => Balance::Fix(candidates.pop().expect("a candidate")),
+            _ => Balance::Ambiguous { disc: name.to_owned(), candidates },
+        }
+    }
+
+    /// The change which moves the combined weight of this program by `delta`.
+    /// That's changing the program itself, unless it's too light to lose that
+    /// much. Then we go up through the discs holding a single program, as
+    /// changing any of those moves the combined weight just the same.
+    fn absorb(&self, name: &str, delta: i64) -> Option<Correction> {
+        let mut pn = name;
+        loop {
+            let p = &self.procs[pn];
+            let new_weight = p.weight as i64 + delta;
+            if new_weight >= 0 {
+                return Some(Correction {
+                    name: pn.to_owned(),
+                    weight: p.weight,
+                    new_weight: new_weight as u64,
+                });
+            }
+            if p.above.len() != 1 {
+                return None;
+            }
+            pn = p.above.iter().next().expect("a program");
+        }
+    }
+
+    /// Whether every disc below this program would be balanced if its combined
+    /// weight changed by `delta`.
+    fn balanced_below(&self, name: &str, delta: i64, weights: &Weights) -> bool {
+        let mut pn = name;
+        while let Some(ref below) = self.procs[pn].below {
+            let target = weights[pn] as i64 + delta;
+            let siblings = &self.procs[below].above;
+            if siblings.iter().any(|c| c != pn && weights[c.as_str()] as i64 != target) {
+                return false;
+            }
+
+            pn = below;
+        }
+
+        true
+    }
+
+    /// Find the cheapest set of weight changes which balances every disc in
+    /// the tower, however many programs are wrong. The bottom program never
+    /// needs to change as nothing depends on its combined weight.
+    fn rebalance(&self, objective: Objective) -> Vec<Correction> {
+        // The combined weights we try to bring each disc to are those which
+        // the programs on it reach by only changing weights further up. Going
+        // anywhere else means changing every program on the disc, which never
+        // takes fewer changes.
+        let mut targets: HashMap<&str, Vec<u64>> = HashMap::new();
+        let mut natural: HashMap<&str, Vec<u64>> = HashMap::new();
+        for name in self.post_order() {
+            let p = &self.procs[name];
+            let k = p.above.len() as u64;
+            let mut ts = p.above.iter()
+                .flat_map(|c| natural[c.as_str()].iter().cloned())
+                .collect::<Vec<u64>>();
+            ts.sort();
+            ts.dedup();
+
+            let ns = if ts.is_empty() {
+                vec![p.weight]
+            } else {
+                ts.iter().filter_map(|&t| k.checked_mul(t).and_then(|kt| kt.checked_add(p.weight))).collect()
+            };
+            natural.insert(name, ns);
+            targets.insert(name, ts);
+        }
+
+        let mut search = Search {
+            tower: self,
+            objective,
+            targets: &targets,
+            memo: HashMap::new(),
+        };
+
+        let mut best: Option<(u64, u64)> = None;
+        for &total in &natural[self.root.as_str()] {
+            if let Some(c) = search.cost(&self.root, total) {
+                if best.is_none_or(|(b, _)| c < b) {
+                    best = Some((c, total));
+                }
+            }
+        }
+
+        // Go back up the tower following the choices we made for each disc
+        let mut fixes = Vec::new();
+        let mut pending = vec![(self.root.as_str(), best.expect("a solution").1)];
+        while let Some((name, total)) = pending.pop() {
+            let p = &self.procs[name];
+            let t = search.memo[&(name, total)].expect("a solution").1;
+            let new_weight = total - p.above.len() as u64 * t;
+            if new_weight != p.weight {
+                fixes.push(Correction {
+                    name: name.to_owned(),
+                    weight: p.weight,
+                    new_weight,
+                });
+            }
+
+            pending.extend(p.above.iter().map(|c| (c.as_str(), t)));
+        }
+
+        fixes.sort_by(|a, b| a.name.cmp(&b.name));
+        if objective == Objective::TotalChange {
+            // The search only tries a few totals for each disc, which can miss
+            // the cheapest way when several programs have to change together.
+            // What it found still bounds how far anything can move though.
+            let bound = fixes.iter().map(|c| c.weight.abs_diff(c.new_weight)).sum();
+            return self.least_total_change(bound);
+        }
+        fixes
+    }
+
+    /// The changes which balance the tower with the least total change, given
+    /// that it can be done with a total of `bound`. No program's combined
+    /// weight can move by more than the total change, so we go through every
+    /// combined weight within `bound` of the current one. Between ways with
+    /// the same total we take the one which changes the fewest programs.
+    fn least_total_change(&self, bound: u64) -> Vec<Correction> {
+        let weights = self.subtree_weights();
+        // The cheapest way to make each program weigh each total from `lo`
+        // up, along with the total of every program on its disc.
+        let mut tables: HashMap<&str, Totals> = HashMap::new();
+        for name in self.post_order() {
+            let p = &self.procs[name];
+            let k = p.above.len() as u64;
+            let (xlo, disc) = Totals::disc(p.above.iter().map(|c| &tables[c.as_str()]));
+
+            let lo = weights[name].saturating_sub(bound);
+            let costs = (lo..=weights[name] + bound)
+                .map(|total| {
+                    let mut best: Option<(Cost, u64)> = None;
+                    for (x, &cost) in (xlo..).zip(&disc) {
+                        if let (Some((change, n)), Some(own)) = (cost, total.checked_sub(k * x)) {
+                            let c = (change + own.abs_diff(p.weight), n + (own != p.weight) as u64);
+                            if c.0 <= bound && best.is_none_or(|(b, _)| c < b) {
+                                best = Some((c, x));
+                            }
+                        }
+                    }
+                    best
+                })
+                .collect();
+            tables.insert(name, Totals { lo, costs });
+        }
+
+        // The bottom program's weight doesn't matter, so it's down to the
+        // cheapest total for the programs on its disc.
+        let root = &self.procs[self.root.as_str()];
+        let (xlo, disc) = Totals::disc(root.above.iter().map(|c| &tables[c.as_str()]));
+        let t = (xlo..)
+            .zip(&disc)
+            .filter_map(|(x, &cost)| cost.map(|c| (c, x)))
+            .min()
+            .map_or(0, |(_, x)| x);
+
+        let mut fixes = Vec::new();
+        let mut pending = root.above.iter().map(|c| (c.as_str(), t)).collect::<Vec<_>>();
+        while let Some((name, total)) = pending.pop() {
+            let p = &self.procs[name];
+            let x = tables[name].get(total).expect("a solution").1;
+            let new_weight = total - p.above.len() as u64 * x;
+            if new_weight != p.weight {
+                fixes.push(Correction {
+                    name: name.to_owned(),
+                    weight: p.weight,
+                    new_weight,
+                });
+            }
+
+            pending.extend(p.above.iter().map(|c| (c.as_str(), x)));
+        }
+
+        fixes.sort_by(|a, b| a.name.cmp(&b.name));
+        fixes
+    }
+
+    /// The programs from the one which needs changing down to the bottom of
+    /// the tower. When we don't know which program to change, we start from
+    /// the disc which is unbalanced.
+    fn unbalanced_path(&self, balance: &Balance) -> HashSet<&str> {
+        let mut pn = match *balance {
+            Balance::Balanced => return HashSet::new(),
+            Balance::Fix(ref c) => c.name.as_str(),
+            Balance::Ambiguous { ref disc, .. } | Balance::Unfixable(ref disc) => disc.as_str(),
+        };
+
+        let mut path = HashSet::new();
+        let (name, _) = self.procs.get_key_value(pn).expect("proc");
+        path.insert(name.as_str());
+        while let Some(ref below) = self.procs[pn].below {
+            path.insert(below.as_str());
+            pn = below;
+        }
+
+        path
+    }
+
+    /// Write the tower as a Graphviz graph, with the bottom program at the
+    /// bottom. The programs in `path` and the links between them are
+    /// highlighted.
+    fn write_dot<W: Write>(&self, w: &mut W, weights: &Weights, path: &HashSet<&str>) -> std::io::Result<()> {
+        writeln!(w, "digraph tower {{")?;
+        writeln!(w, "    rankdir=BT;")?;
+        for name in self.post_order() {
+            let p = &self.procs[name];
+            write!(w, "    {} [label=\"{} ({} / {})\"", name, name, p.weight, weights[name])?;
+            if path.contains(name) {
+                write!(w, ", color=red, fontcolor=red")?;
+            }
+            writeln!(w, "];")?;
+
+            for child in &p.above {
+                write!(w, "    {} -> {}", child, name)?;
+                if path.contains(name) && path.contains(child.as_str()) {
+                    write!(w, " [color=red]")?;
+                }
+                writeln!(w, ";")?;
             }
         }
+        writeln!(w, "}}")
     }
 
-    // last_common_weight is what we need pn's combined weight to be. The
-    // difference between these values is how much we need to change its weight
-    // by.
-    let unbalanced_w = combined_weight(pn, &procs);
-    let d = (last_common_weight as i64) - (unbalanced_w as i64);
-    println!("Unabalanced {}, should weigh {}", pn, procs.get(pn).expect("proc").weight as i64 + d);
+    /// Write the tower as nested JSON objects, starting from the bottom
+    /// program.
+    fn write_json<W: Write>(&self, w: &mut W, weights: &Weights) -> std::io::Result<()> {
+        fn write_proc<W: Write>(t: &Tower, name: &str, w: &mut W, weights: &Weights) -> std::io::Result<()> {
+            let p = &t.procs[name];
+            write!(w, "{{\"name\":\"{}\",\"weight\":{},\"total_weight\":{},\"above\":[",
+                   name, p.weight, weights[name])?;
+            for (i, child) in p.above.iter().enumerate() {
+                if i > 0 {
+                    write!(w, ",")?;
+                }
+                write_proc(t, child, w, weights)?;
+            }
+            write!(w, "]}}")
+        }
 
+        // Names are only ever word characters, so they need no escaping
+        write_proc(self, &self.root, w, weights)?;
+        writeln!(w)
+    }
 }
 
-/// Read the processes from stdin and return them with filled dependencies.
-fn read_procs(stdin: std::io::Stdin) -> ProcMap {
-    let expr = Regex::new(r"^(\w+?) \((\d+)\)(?: -> (.*))?$").expect("regex");
+/// The ways in which an edit to the tower can fail
+#[derive(Debug, PartialEq)]
+enum EditError {
+    /// There is no program with this name
+    UnknownProgram(String),
+    /// There is already a program with this name
+    AlreadyExists(String),
+    /// The bottom program cannot be moved or removed
+    Bottom(String),
+    /// Moving the program onto this one would make it hold itself up
+    WouldCycle { name: String, parent: String },
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            EditError::UnknownProgram(ref name) => write!(f, "there is no program {}", name),
+            EditError::AlreadyExists(ref name) => write!(f, "there is already a program {}", name),
+            EditError::Bottom(ref name) => write!(f, "{} is at the bottom of the tower", name),
+            EditError::WouldCycle { ref name, ref parent } => write!(f, "{} is above {}", parent, name),
+        }
+    }
+}
+
+/// A tower we can make changes to. The combined weights and the list of
+/// unbalanced discs are kept up to date as we go, which only needs us to look
+/// at the programs below the one we changed.
+struct Editor {
+    tower: Tower,
+    weights: Weights,
+    /// The programs whose discs hold programs of different weights
+    unbalanced: BTreeSet<String>,
+}
+
+impl Editor {
+    fn new(tower: Tower) -> Editor {
+        let weights = tower.subtree_weights();
+        let mut editor = Editor {
+            tower,
+            weights,
+            unbalanced: BTreeSet::new(),
+        };
+
+        let names = editor.tower.procs.keys().cloned().collect::<Vec<String>>();
+        for name in names {
+            editor.recheck(&name);
+        }
+
+        editor
+    }
+
+    /// Change the weight of a program
+    fn set_weight(&mut self, name: &str, weight: u64) -> Result<(), EditError> {
+        let old = match self.tower.procs.get_mut(name) {
+            Some(p) => std::mem::replace(&mut p.weight, weight),
+            None => return Err(EditError::UnknownProgram(name.to_owned())),
+        };
+
+        self.adjust_totals(name, weight as i64 - old as i64);
+        self.recheck_below(name);
+        Ok(())
+    }
+
+    /// Put a new program on the disc of `parent`
+    fn add(&mut self, name: &str, weight: u64, parent: &str) -> Result<(), EditError> {
+        if self.tower.procs.contains_key(name) {
+            return Err(EditError::AlreadyExists(name.to_owned()));
+        }
+        match self.tower.procs.get_mut(parent) {
+            Some(p) => p.above.insert(name.to_owned()),
+            None => return Err(EditError::UnknownProgram(parent.to_owned())),
+        };
+
+        self.tower.procs.insert(name.to_owned(), Process {
+            name: name.to_owned(),
+            weight,
+            above: BTreeSet::new(),
+            below: Some(parent.to_owned()),
+        });
+        self.weights.insert(name.to_owned(), 0);
+
+        self.adjust_totals(name, weight as i64);
+        self.recheck_below(name);
+        Ok(())
+    }
+
+    /// Take a program off the tower, along with everything above it
+    fn remove(&mut self, name: &str) -> Result<(), EditError> {
+        let parent = self.detach(name)?;
+
+        let mut pending = vec![name.to_owned()];
+        while let Some(n) = pending.pop() {
+            let p = self.tower.procs.remove(&n).expect("proc");
+            self.weights.remove(&n);
+            self.unbalanced.remove(&n);
+            pending.extend(p.above);
+        }
+
+        self.recheck_below(&parent);
+        Ok(())
+    }
+
+    /// Move a program, along with everything above it, onto the disc of
+    /// `parent`.
+    fn move_to(&mut self, name: &str, parent: &str) -> Result<(), EditError> {
+        match self.tower.procs.get(name) {
+            Some(p) if p.below.is_none() => return Err(EditError::Bottom(name.to_owned())),
+            Some(_) => {}
+            None => return Err(EditError::UnknownProgram(name.to_owned())),
+        }
+        if !self.tower.procs.contains_key(parent) {
+            return Err(EditError::UnknownProgram(parent.to_owned()));
+        }
+
+        // The new parent can't be the program itself or one above it, which
+        // we find out by going down from the new parent.
+        let mut pn = Some(parent);
+        while let Some(n) = pn {
+            if n == name {
+                return Err(EditError::WouldCycle { name: name.to_owned(), parent: parent.to_owned() });
+            }
+            pn = self.tower.procs[n].below.as_deref();
+        }
 
-    let mut procs: ProcMap = ProcMap::new();
-    let mut input = String::new();
+        let old_parent = self.detach(name)?;
+
+        self.tower.procs.get_mut(parent).expect("proc").above.insert(name.to_owned());
+        self.tower.procs.get_mut(name).expect("proc").below = Some(parent.to_owned());
+        self.adjust_totals(parent, self.weights[name] as i64);
+
+        self.recheck_below(&old_parent);
+        self.recheck_below(parent);
+        Ok(())
+    }
+
+    /// Take a program off the disc it's on, returning the program it was on.
+    /// Its own combined weight is left as it was.
+    fn detach(&mut self, name: &str) -> Result<String, EditError> {
+        let parent = match self.tower.procs.get(name) {
+            Some(p) => match p.below {
+                Some(ref parent) => parent.clone(),
+                None => return Err(EditError::Bottom(name.to_owned())),
+            },
+            None => return Err(EditError::UnknownProgram(name.to_owned())),
+        };
+
+        self.tower.procs.get_mut(&parent).expect("proc").above.remove(name);
+        self.tower.procs.get_mut(name).expect("proc").below = None;
+        self.adjust_totals(&parent, -(self.weights[name] as i64));
+
+        Ok(parent)
+    }
+
+    /// Add `delta` to the combined weight of this program and every one below
+    fn adjust_totals(&mut self, name: &str, delta: i64) {
+        let mut pn = name;
+        loop {
+            let w = self.weights.get_mut(pn).expect("weight");
+            *w = (*w as i64 + delta) as u64;
+
+            match self.tower.procs[pn].below {
+                Some(ref below) => pn = below,
+                None => break,
+            }
+        }
+    }
+
+    /// Update whether the disc on this program and every one below it is
+    /// balanced, as those are the only ones a change here can affect.
+    fn recheck_below(&mut self, name: &str) {
+        let mut pn = Some(name.to_owned());
+        while let Some(n) = pn {
+            self.recheck(&n);
+            pn = self.tower.procs[&n].below.clone();
+        }
+    }
+
+    fn recheck(&mut self, name: &str) {
+        let mut ws = self.tower.procs[name].above.iter().map(|c| self.weights[c]);
+        let balanced = match ws.next() {
+            Some(first) => ws.all(|w| w == first),
+            None => true,
+        };
+
+        if balanced {
+            self.unbalanced.remove(name);
+        } else {
+            self.unbalanced.insert(name.to_owned());
+        }
+    }
+}
+
+/// What makes one set of changes to the weights better than another
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Objective {
+    /// Change as few programs as possible
+    Changes,
+    /// Change the weights by as little as possible in total
+    TotalChange,
+}
+
+/// The total change to the weights and how many programs it changes
+type Cost = (u64, u64);
+
+/// For `Tower::least_total_change`, the cheapest way to make a program weigh
+/// each total in a range, along with the total of each program on its disc
+struct Totals {
+    lo: u64,
+    costs: Vec<Option<(Cost, u64)>>,
+}
+
+impl Totals {
+    fn get(&self, total: u64) -> Option<(Cost, u64)> {
+        total.checked_sub(self.lo).and_then(|i| self.costs.get(i as usize)).and_then(|&c| c)
+    }
+
+    /// The cost of making every program on a disc weigh each total which all
+    /// of them can reach, starting from the lowest one. An empty disc costs
+    /// nothing at zero.
+    fn disc<'b, I: Iterator<Item = &'b Totals>>(above: I) -> (u64, Vec<Option<Cost>>) {
+        let above = above.collect::<Vec<_>>();
+        let lo = above.iter().map(|t| t.lo).max().unwrap_or(0);
+        let hi = above.iter().map(|t| t.lo + t.costs.len() as u64).min().unwrap_or(1);
+        let costs = (lo..hi)
+            .map(|x| {
+                above.iter().try_fold((0, 0), |(change, n), t| {
+                    t.get(x).map(|((c, m), _)| (change + c, n + m))
+                })
+            })
+            .collect();
+        (lo, costs)
+    }
+}
+
+/// The state for `Tower::rebalance`
+struct Search<'a> {
+    tower: &'a Tower,
+    objective: Objective,
+    /// The combined weights to try for the programs on each disc
+    targets: &'a HashMap<&'a str, Vec<u64>>,
+    /// The cheapest way to make a program and the ones above it weigh a
+    /// particular amount in total, along with the weight of each program on
+    /// its disc. It's `None` when there is no way to do it.
+    memo: HashMap<(&'a str, u64), Option<(u64, u64)>>,
+}
+
+impl<'a> Search<'a> {
+    fn change_cost(&self, old: u64, new: u64) -> u64 {
+        match self.objective {
+            Objective::Changes => (old != new) as u64,
+            Objective::TotalChange => old.abs_diff(new),
+        }
+    }
+
+    /// The cost of making `name` and everything above it weigh `total`
+    fn cost(&mut self, name: &'a str, total: u64) -> Option<u64> {
+        if let Some(&res) = self.memo.get(&(name, total)) {
+            return res.map(|(c, _)| c);
+        }
+
+        let tower = self.tower;
+        let p = &tower.procs[name];
+        let k = p.above.len() as u64;
+
+        let mut best: Option<(u64, u64)> = None;
+        if k == 0 {
+            best = Some((self.change_cost(p.weight, total), 0));
+        } else {
+            // Besides the usual targets, we can also try keeping this
+            // program's weight and changing everything above it to fit.
+            let mut ts = self.targets[name].clone();
+            if total >= p.weight && (total - p.weight).is_multiple_of(k) {
+                ts.push((total - p.weight) / k);
+            }
+
+            'targets: for t in ts {
+                let own = match k.checked_mul(t).and_then(|kt| total.checked_sub(kt)) {
+                    Some(w) => w,
+                    None => continue,
+                };
+
+                let mut c = self.change_cost(p.weight, own);
+                for child in &p.above {
+                    if best.is_some_and(|(b, _)| c >= b) {
+                        continue 'targets;
+                    }
+                    match self.cost(child, t) {
+                        Some(cc) => c += cc,
+                        None => continue 'targets,
+                    }
+                }
+
+                if best.is_none_or(|(b, _)| c < b) {
+                    best = Some((c, t));
+                }
+            }
+        }
+
+        self.memo.insert((name, total), best);
+        best.map(|(c, _)| c)
+    }
+}
+
+/// Find the cycles in the graph formed by the "above" links. Each cycle is
+/// reported once, as the list of programs in it.
+fn find_cycles(names: &[String], procs: &ProcMap) -> Vec<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(name: &'a str, procs: &'a ProcMap, marks: &mut HashMap<&'a str, Mark>,
+                 path: &mut Vec<&'a str>, cycles: &mut Vec<Vec<String>>) {
+        match marks.get(name) {
+            Some(&Mark::Done) => return,
+            Some(&Mark::Visiting) => {
+                let start = path.iter().position(|&n| n == name).expect("on path");
+                cycles.push(path[start..].iter().map(|&n| n.to_owned()).collect());
+                return;
+            }
+            None => {}
+        }
+
+        marks.insert(name, Mark::Visiting);
+        path.push(name);
+
+        for child in procs[name].above.iter().filter(|n| procs.contains_key(n.as_str())) {
+            visit(child, procs, marks, path, cycles);
+        }
+
+        path.pop();
+        marks.insert(name, Mark::Done);
+    }
+
+    let mut marks = HashMap::new();
+    let mut cycles = Vec::new();
+    for name in names {
+        visit(name, procs, &mut marks, &mut Vec::new(), &mut cycles);
+    }
+
+    cycles
+}
+
+/// What to print once we've read the tower
+enum Output {
+    Report,
+    Dot,
+    Json,
+    Rebalance(Objective),
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let output = match args.next().as_deref() {
+        None => Output::Report,
+        Some("--dot") => Output::Dot,
+        Some("--json") => Output::Json,
+        Some("--rebalance") => match args.next().as_deref() {
+            Some("changes") | None => Output::Rebalance(Objective::Changes),
+            Some("total") => Output::Rebalance(Objective::TotalChange),
+            Some(arg) => panic!("unknown objective {}, expected changes or total", arg),
+        },
+        Some("--edit") => {
+            // The tower comes from a file as we read the commands from stdin
+            let path = args.next().expect("path to the tower");
+            let f = std::fs::File::open(path).expect("open tower");
+            edit(load_tower(std::io::BufReader::new(f)));
+            return;
+        }
+        Some(arg) => panic!("unknown argument {}", arg),
+    };
+
+    let stdin = std::io::stdin();
+    let tower = load_tower(stdin.lock());
+    let weights = tower.subtree_weights();
+    let balance = tower.find_imbalance(&weights);
+
+    let stdout = std::io::stdout();
+    match output {
+        Output::Report => {}
+        Output::Dot => {
+            let path = tower.unbalanced_path(&balance);
+            tower.write_dot(&mut stdout.lock(), &weights, &path).expect("write dot");
+            return;
+        }
+        Output::Json => {
+            tower.write_json(&mut stdout.lock(), &weights).expect("write json");
+            return;
+        }
+        Output::Rebalance(objective) => {
+            let fixes = tower.rebalance(objective);
+            if fixes.is_empty() {
+                println!("The tower is balanced");
+            }
+            for c in fixes {
+                println!("{} from {} to {}", c.name, c.weight, c.new_weight);
+            }
+            return;
+        }
+    }
+
+    println!("The bottom process is {}", tower.root);
+    print_balance(&balance);
+}
+
+/// Read the tower, exiting with the list of problems if it's not valid
+fn load_tower<R: BufRead>(r: R) -> Tower {
+    match read_procs(r).and_then(Tower::from_procs) {
+        Ok(t) => t,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("error: {}", e);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_balance(balance: &Balance) {
+    match *balance {
+        Balance::Balanced => println!("The tower is balanced"),
+        Balance::Fix(ref c) => println!("Unbalanced {}, should weigh {}", c.name, c.new_weight),
+        Balance::Ambiguous { ref disc, ref candidates } => {
+            println!("The disc on {} has two different programs, either change would balance it:", disc);
+            for c in candidates {
+                println!("  {} from {} to {}", c.name, c.weight, c.new_weight);
+            }
+        }
+        Balance::Unfixable(ref disc) => println!("The disc on {} cannot be balanced by a single change", disc),
+    }
+}
+
+const EDIT_HELP: &str = "\
+weight NAME WEIGHT      change the weight of a program
+add NAME WEIGHT PARENT  put a new program on the disc of PARENT
+remove NAME             remove a program and everything above it
+move NAME PARENT        move a program and everything above it onto PARENT
+show NAME               show a program and the ones on its disc
+fix                     look for a single program to change
+quit                    exit";
+
+/// Read editing commands from stdin and apply them to the tower, reporting
+/// which discs are unbalanced after each change.
+fn edit(tower: Tower) {
+    let mut editor = Editor::new(tower);
+    print_unbalanced(&editor);
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut line = String::new();
     loop {
-        input.clear();
-        let read = stdin.read_line(&mut input).expect("stdin");
-        if read == 0 {
+        write!(stdout, "> ").expect("prompt");
+        stdout.flush().expect("flush");
+
+        line.clear();
+        if stdin.read_line(&mut line).expect("stdin") == 0 {
             break;
         }
 
-        let s = input.trim();
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        let res = match words.as_slice() {
+            [] => continue,
+            ["quit"] => break,
+            ["fix"] => {
+                print_balance(&editor.tower.find_imbalance(&editor.weights));
+                continue;
+            }
+            ["show", name] => {
+                match editor.tower.procs.get(*name) {
+                    Some(p) => {
+                        println!("{} ({} / {})", name, p.weight, editor.weights[*name]);
+                        for c in &p.above {
+                            println!("  {} ({} / {})", c, editor.tower.procs[c].weight, editor.weights[c]);
+                        }
+                    }
+                    None => println!("error: {}", EditError::UnknownProgram(name.to_string())),
+                }
+                continue;
+            }
+            ["weight", name, weight] => match weight.parse() {
+                Ok(w) => editor.set_weight(name, w),
+                Err(_) => {
+                    println!("error: invalid weight {}", weight);
+                    continue;
+                }
+            },
+            ["add", name, weight, parent] => match weight.parse() {
+                Ok(w) => editor.add(name, w, parent),
+                Err(_) => {
+                    println!("error: invalid weight {}", weight);
+                    continue;
+                }
+            },
+            ["remove", name] => editor.remove(name),
+            ["move", name, parent] => editor.move_to(name, parent),
+            _ => {
+                println!("{}", EDIT_HELP);
+                continue;
+            }
+        };
+
+        match res {
+            Ok(()) => print_unbalanced(&editor),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+fn print_unbalanced(editor: &Editor) {
+    if editor.unbalanced.is_empty() {
+        println!("All discs are balanced");
+        return;
+    }
 
-        let caps = expr.captures(s).expect("captures");
+    println!("Unbalanced discs:");
+    for name in &editor.unbalanced {
+        let ws = editor.tower.procs[name].above.iter()
+            .map(|c| format!("{} ({})", c, editor.weights[c]))
+            .collect::<Vec<String>>();
+        println!("  {}: {}", name, ws.join(", "));
+    }
+}
+
+/// Read the processes from the input. The links between them are not checked
+/// here, that happens when building the `Tower`.
+fn read_procs<R: BufRead>(r: R) -> Result<Vec<Process>, Vec<TowerError>> {
+    let expr = Regex::new(r"^(\w+?) \((\d+)\)(?: -> (.*))?$").expect("regex");
+
+    let mut procs = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line.expect("line");
+        let s = line.trim();
+        if s.is_empty() {
+            continue;
+        }
+
+        // The weight can still be too big even when the line matches
+        let parsed = expr.captures(s).and_then(|caps| {
+            let weight = caps.get(2).expect("weight").as_str().parse::<u64>().ok()?;
+            Some((caps, weight))
+        });
+        let (caps, weight) = match parsed {
+            Some(p) => p,
+            None => {
+                errors.push(TowerError::Malformed { line: i + 1, text: s.to_owned() });
+                continue;
+            }
+        };
 
         let name = caps.get(1).expect("name").as_str().to_owned();
-        let weight = u64::from_str_radix(caps.get(2).expect("weight").as_str(), 10).expect("weight");
-        let mut above = HashSet::new();
+        let mut above = BTreeSet::new();
         if let Some(m) = caps.get(3) {
             for name in m.as_str().split(", ") {
                 above.insert(name.into());
             }
         }
 
-        let p = Process {
-            name: name.clone(),
-            weight: weight,
-            above: above,
-            below: RefCell::new(None),
-        };
+        procs.push(Process {
+            name,
+            weight,
+            above,
+            below: None,
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(procs)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use aoc2017::rng::XorShift;
+    use super::{Balance, Correction, EditError, Editor, Objective, Process, Tower, TowerError};
+    use std::collections::BTreeSet;
+
+    const SAMPLE_INPUT: &str = include_str!("../../input7-sample.txt");
+
+    fn tower(input: &str) -> Result<Tower, Vec<TowerError>> {
+        super::read_procs(Cursor::new(input)).and_then(Tower::from_procs)
+    }
+
+    fn names(ns: &[&str]) -> Vec<String> {
+        ns.iter().map(|&n| n.to_owned()).collect()
+    }
+
+    #[test]
+    fn sample_input() {
+        let t = tower(SAMPLE_INPUT).unwrap();
+        assert_eq!("tknk", t.root);
+        assert_eq!(Some("tknk".to_owned()), t.procs["ugml"].below);
+        assert_eq!(None, t.procs["tknk"].below);
+
+        let weights = t.subtree_weights();
+        assert_eq!(251, weights["ugml"]);
+        assert_eq!(243, weights["padx"]);
+        assert_eq!(243, weights["fwft"]);
+        assert_eq!(778, weights["tknk"]);
+
+        let fix = Correction { name: "ugml".into(), weight: 68, new_weight: 60 };
+        assert_eq!(Balance::Fix(fix), t.find_imbalance(&weights));
+    }
+
+    #[test]
+    fn two_programs_on_a_disc() {
+        let t = tower("r (1) -> a, b\na (5)\nb (7)").unwrap();
+        let weights = t.subtree_weights();
+        assert_eq!(Balance::Ambiguous {
+            disc: "r".into(),
+            candidates: vec![Correction { name: "a".into(), weight: 5, new_weight: 7 },
+                             Correction { name: "b".into(), weight: 7, new_weight: 5 }],
+        }, t.find_imbalance(&weights));
+    }
+
+    #[test]
+    fn two_programs_resolved_further_down() {
+        // Either a or b could be wrong looking at x alone, but only changing b
+        // makes x weigh the same as y and z.
+        let t = tower("r (1) -> x, y, z\nx (1) -> a, b\na (5)\nb (7)\ny (11)\nz (11)").unwrap();
+        let weights = t.subtree_weights();
+        let fix = Correction { name: "b".into(), weight: 7, new_weight: 5 };
+        assert_eq!(Balance::Fix(fix), t.find_imbalance(&weights));
+
+        // The same, but resolving through a disc with a single program on it
+        let t = tower("r (1) -> w, y\nw (0) -> x\nx (1) -> a, b\na (5)\nb (7)\ny (15)").unwrap();
+        let weights = t.subtree_weights();
+        let fix = Correction { name: "a".into(), weight: 5, new_weight: 7 };
+        assert_eq!(Balance::Fix(fix), t.find_imbalance(&weights));
+    }
+
+    #[test]
+    fn single_program_on_a_disc() {
+        let t = tower("r (1) -> a\na (3) -> b\nb (2)").unwrap();
+        let weights = t.subtree_weights();
+        assert_eq!(Balance::Balanced, t.find_imbalance(&weights));
+
+        let t = tower("r (1) -> a\na (3) -> b, c, d\nb (2)\nc (2)\nd (4)").unwrap();
+        let weights = t.subtree_weights();
+        let fix = Correction { name: "d".into(), weight: 4, new_weight: 2 };
+        assert_eq!(Balance::Fix(fix), t.find_imbalance(&weights));
+
+        // p3 is too light to lose 2, but the program it holds isn't
+        let t = tower("p0 (4) -> p1, p2, p3\np1 (1)\np2 (1)\np3 (1) -> p4\np4 (2)").unwrap();
+        let weights = t.subtree_weights();
+        let fix = Correction { name: "p4".into(), weight: 2, new_weight: 0 };
+        assert_eq!(Balance::Fix(fix), t.find_imbalance(&weights));
+    }
+
+    #[test]
+    fn more_than_one_wrong_program() {
+        let t = tower("r (1) -> x, y, z\nx (1) -> a, b, c\na (1)\nb (1)\nc (2)\ny (3) -> d, e, f\nd (1)\ne (1)\nf (2)\nz (4)").unwrap();
+        let weights = t.subtree_weights();
+        assert_eq!(Balance::Unfixable("x".into()), t.find_imbalance(&weights));
 
-        procs.insert(name, p);
+        // Changing p2 would balance p1, but p3 is still unbalanced off to the
+        // side of it
+        let t = tower("p0 (4) -> p1, p3\np1 (2) -> p2, p6\np2 (4)\np3 (2) -> p4, p5\np4 (4)\np5 (2)\n\
+                       p6 (0) -> p7\np7 (3)").unwrap();
+        let weights = t.subtree_weights();
+        assert_eq!(Balance::Unfixable("p1".into()), t.find_imbalance(&weights));
     }
 
-    fill_belows(procs)
+    #[test]
+    fn export() {
+        let t = tower("r (1) -> a, b, c\na (2)\nb (2) -> d\nd (1)\nc (2)").unwrap();
+        let weights = t.subtree_weights();
+        let path = t.unbalanced_path(&t.find_imbalance(&weights));
+
+        let mut dot = Vec::new();
+        t.write_dot(&mut dot, &weights, &path).unwrap();
+        assert_eq!(r#"digraph tower {
+    rankdir=BT;
+    a [label="a (2 / 2)"];
+    d [label="d (1 / 1)"];
+    b [label="b (2 / 3)", color=red, fontcolor=red];
+    d -> b;
+    c [label="c (2 / 2)"];
+    r [label="r (1 / 8)", color=red, fontcolor=red];
+    a -> r;
+    b -> r [color=red];
+    c -> r;
 }
+"#, String::from_utf8(dot).unwrap());
 
-/// Take the process map and insert all those processes which are below
-fn fill_belows(procs: ProcMap) -> ProcMap {
-    {
-        let names = procs.keys().map(|s| s.as_str()).collect::<Vec<&str>>();
-        for name in names {
-            let p = procs.get(name).expect("proc");
-            for ref above in &p.above {
-                procs.get(above.as_str()).expect("proc").below.borrow_mut().get_or_insert(name.into());
+        let mut json = Vec::new();
+        t.write_json(&mut json, &weights).unwrap();
+        assert_eq!(concat!(r#"{"name":"r","weight":1,"total_weight":8,"above":["#,
+                           r#"{"name":"a","weight":2,"total_weight":2,"above":[]},"#,
+                           r#"{"name":"b","weight":2,"total_weight":3,"above":["#,
+                           r#"{"name":"d","weight":1,"total_weight":1,"above":[]}]},"#,
+                           r#"{"name":"c","weight":2,"total_weight":2,"above":[]}]}"#, "\n"),
+                   String::from_utf8(json).unwrap());
+    }
+
+    #[test]
+    fn edit() {
+        let mut e = Editor::new(tower(SAMPLE_INPUT).unwrap());
+        assert_eq!(vec!["tknk"], e.unbalanced.iter().collect::<Vec<&String>>());
+
+        e.set_weight("ugml", 60).unwrap();
+        assert!(e.unbalanced.is_empty());
+        assert_eq!(770, e.weights["tknk"]);
+
+        e.add("new", 3, "gyxo").unwrap();
+        assert_eq!(vec!["tknk", "ugml"], e.unbalanced.iter().collect::<Vec<&String>>());
+        e.remove("new").unwrap();
+        assert!(e.unbalanced.is_empty());
+
+        e.move_to("havc", "ktlj").unwrap();
+        assert_eq!(vec!["fwft", "tknk"], e.unbalanced.iter().collect::<Vec<&String>>());
+        e.move_to("havc", "padx").unwrap();
+        assert!(e.unbalanced.is_empty());
+
+        e.remove("fwft").unwrap();
+        assert!(!e.tower.procs.contains_key("cntj"));
+        assert!(e.unbalanced.is_empty());
+        assert_eq!(527, e.weights["tknk"]);
+
+        // The incremental weights must match working them out from scratch
+        assert_eq!(e.tower.subtree_weights(), e.weights);
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let mut e = Editor::new(tower(SAMPLE_INPUT).unwrap());
+        assert_eq!(Err(EditError::UnknownProgram("nope".into())), e.set_weight("nope", 1));
+        assert_eq!(Err(EditError::AlreadyExists("padx".into())), e.add("padx", 1, "tknk"));
+        assert_eq!(Err(EditError::UnknownProgram("nope".into())), e.add("new", 1, "nope"));
+        assert_eq!(Err(EditError::Bottom("tknk".into())), e.remove("tknk"));
+        assert_eq!(Err(EditError::Bottom("tknk".into())), e.move_to("tknk", "padx"));
+        assert_eq!(Err(EditError::WouldCycle { name: "padx".into(), parent: "havc".into() }),
+                   e.move_to("padx", "havc"));
+        assert_eq!(Err(EditError::WouldCycle { name: "padx".into(), parent: "padx".into() }),
+                   e.move_to("padx", "padx"));
+        assert_eq!(e.tower.subtree_weights(), e.weights);
+    }
+
+    /// Generate a random balanced tower of roughly `size` programs
+    fn balanced_tower(rng: &mut XorShift, size: usize) -> Tower {
+        let mut procs = vec![Process { name: "p0".into(), weight: 1 + rng.next(50), above: BTreeSet::new(), below: None }];
+        let mut i = 0;
+        while procs.len() < size && i < procs.len() {
+            let k = if i == 0 { 3 } else { [0, 0, 2, 3, 4][rng.next(5) as usize] };
+            for _ in 0..k {
+                let name = format!("p{}", procs.len());
+                procs[i].above.insert(name.clone());
+                procs.push(Process { name, weight: 1 + rng.next(50), above: BTreeSet::new(), below: None });
+            }
+            i += 1;
+        }
+
+        // Going from the top down, raise the lighter programs on each disc
+        // until they all match.
+        let mut t = Tower::from_procs(procs).unwrap();
+        let order = t.post_order().iter().map(|&n| n.to_owned()).collect::<Vec<String>>();
+        for name in order {
+            let weights = t.subtree_weights();
+            let above = t.procs[&name].above.clone();
+            let max = above.iter().map(|c| weights[c]).max().unwrap_or(0);
+            for c in above {
+                t.procs.get_mut(&c).unwrap().weight += max - weights[&c];
             }
         }
+
+        t
     }
 
-    procs
-}
+    fn apply(t: &mut Tower, fixes: &[Correction]) {
+        for c in fixes {
+            assert_eq!(c.weight, t.procs[&c.name].weight);
+            t.procs.get_mut(&c.name).unwrap().weight = c.new_weight;
+        }
+    }
 
-/// Returns the combined weight of the given process together with any above it.
-fn combined_weight(pn: &str, procs: &ProcMap) -> u64 {
-    let p = procs.get(pn).expect("proc");
-    let mut w = p.weight;
+    fn assert_balanced(t: &Tower) {
+        let weights = t.subtree_weights();
+        for p in t.procs.values() {
+            let ws = p.above.iter().map(|c| weights[c]).collect::<Vec<u64>>();
+            assert!(ws.iter().all(|&w| w == ws[0]), "{} is unbalanced: {:?}", p.name, ws);
+        }
+    }
 
-    for name in &p.above {
-        w += combined_weight(name, procs);
+    #[test]
+    fn rebalance_sample() {
+        let t = tower(SAMPLE_INPUT).unwrap();
+        let fix = vec![Correction { name: "ugml".into(), weight: 68, new_weight: 60 }];
+        assert_eq!(fix, t.rebalance(Objective::Changes));
+        assert_eq!(fix, t.rebalance(Objective::TotalChange));
     }
 
-    w
-}
+    #[test]
+    fn rebalance_keeping_weights_positive() {
+        // Lowering r's weight would be a single change but it can't go below
+        // zero, so both programs on its disc need to be lighter instead.
+        let t = tower("b (1) -> r, s, u, v\nr (0) -> x, y\nx (7)\ny (7)\ns (12)\nu (12)\nv (12)").unwrap();
+        let fixes = vec![Correction { name: "x".into(), weight: 7, new_weight: 6 },
+                         Correction { name: "y".into(), weight: 7, new_weight: 6 }];
+        assert_eq!(fixes, t.rebalance(Objective::Changes));
+        assert_eq!(fixes, t.rebalance(Objective::TotalChange));
+    }
 
-/// Returns the name of the process with a different weight than the rest, plus
-/// the common weight. It assumes there is at most a single odd one out and the
-/// rest have the same weight.
-///
-/// If they're all the same, it returns None.
-fn odd_weight<'a>(names: &'a HashSet<String>, procs: &ProcMap) -> Option<(&'a str, u64)> {
-    for name in names {
-        let w = combined_weight(name, procs);
-        let mut common = 0;
-        let mut common_weight = 0;
+    #[test]
+    fn rebalance_injected_faults() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20 {
+            let mut t = balanced_tower(&mut rng, 40);
+            assert!(t.rebalance(Objective::Changes).is_empty());
 
-        for pn in names {
-            if pn == name {
-                continue;
+            // Break a few programs, leaving the bottom one alone as its weight
+            // doesn't matter.
+            let faults = 1 + rng.next(4) as usize;
+            let mut injected = 0;
+            for _ in 0..faults {
+                let name = format!("p{}", 1 + rng.next(t.procs.len() as u64 - 1));
+                let delta = 1 + rng.next(20);
+                t.procs.get_mut(&name).unwrap().weight += delta;
+                injected += delta;
             }
 
-            let ww = combined_weight(pn, procs);
-            println!("comparing {} ({}) <=> {} ({})", name, w, pn, ww);
-            if ww == w {
-                common += 1;
-            } else {
-                common_weight = ww;
+            let fixes = t.rebalance(Objective::Changes);
+            assert!(fixes.len() <= faults);
+            let mut fixed = Tower::from_procs(t.procs.values().map(|p| Process {
+                name: p.name.clone(),
+                weight: p.weight,
+                above: p.above.clone(),
+                below: None,
+            }).collect()).unwrap();
+            apply(&mut fixed, &fixes);
+            assert_balanced(&fixed);
+
+            let fixes = t.rebalance(Objective::TotalChange);
+            let total = fixes.iter().map(|c| c.weight.abs_diff(c.new_weight)).sum::<u64>();
+            assert!(total <= injected);
+            apply(&mut t, &fixes);
+            assert_balanced(&t);
+        }
+    }
+
+    /// The fewest changes and the least total change which balance a small
+    /// tower, trying every weight up to `max` for the programs other than the
+    /// bottom one. `parents[i]` is the program holding program `i + 1`, which
+    /// always comes before it.
+    fn brute_force(weights: &[u64], parents: &[usize], max: u64) -> (u64, u64) {
+        let n = weights.len();
+        let mut new = vec![0; n];
+        new[0] = weights[0];
+        let mut best = (u64::MAX, u64::MAX);
+        loop {
+            let mut totals = new.clone();
+            let mut disc = vec![None; n];
+            let mut balanced = true;
+            for i in (1..n).rev() {
+                let p = parents[i - 1];
+                balanced &= *disc[p].get_or_insert(totals[i]) == totals[i];
+                totals[p] += totals[i];
             }
+            if balanced {
+                let changes = (1..n).filter(|&i| new[i] != weights[i]).count() as u64;
+                let total = (1..n).map(|i| new[i].abs_diff(weights[i])).sum();
+                best = (best.0.min(changes), best.1.min(total));
+            }
+
+            // Count through every combination of weights
+            let mut i = 1;
+            while i < n && new[i] == max {
+                new[i] = 0;
+                i += 1;
+            }
+            if i == n {
+                return best;
+            }
+            new[i] += 1;
         }
+    }
+
+    #[test]
+    fn rebalance_brute_force() {
+        const MAX: u64 = 15;
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..100 {
+            let n = 2 + rng.next(4) as usize;
+            let weights = (0..n).map(|_| rng.next(4)).collect::<Vec<u64>>();
+            let parents = (1..n).map(|i| rng.next(i as u64) as usize).collect::<Vec<usize>>();
+
+            let mut procs = (0..n)
+                .map(|i| Process { name: format!("p{}", i), weight: weights[i], above: BTreeSet::new(), below: None })
+                .collect::<Vec<Process>>();
+            for (i, &p) in parents.iter().enumerate() {
+                procs[p].above.insert(format!("p{}", i + 1));
+            }
+            let t = Tower::from_procs(procs).unwrap();
 
-        if common == 0 {
-            return Some((name, common_weight));
+            let (changes, total) = brute_force(&weights, &parents, MAX);
+            let fixes = t.rebalance(Objective::Changes);
+            assert_eq!(changes, fixes.len() as u64, "{:?} {:?}", weights, parents);
+            assert!(fixes.iter().all(|c| c.new_weight <= MAX));
+            let fixes = t.rebalance(Objective::TotalChange);
+            assert_eq!(total, fixes.iter().map(|c| c.weight.abs_diff(c.new_weight)).sum::<u64>(),
+                       "{:?} {:?}", weights, parents);
+            assert!(fixes.iter().all(|c| c.new_weight <= MAX));
         }
+
+        // Both p2 and p3 have to go down to zero together
+        let t = tower("p0 (1) -> p1, p2, p4\np1 (0)\np2 (3) -> p3\np3 (2)\np4 (0)").unwrap();
+        assert_eq!(vec![Correction { name: "p2".into(), weight: 3, new_weight: 0 },
+                        Correction { name: "p3".into(), weight: 2, new_weight: 0 }],
+                   t.rebalance(Objective::TotalChange));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(vec![TowerError::Empty], tower("").unwrap_err());
+    }
+
+    #[test]
+    fn dangling_reference() {
+        let errors = tower("a (1) -> b, c\nb (1)").unwrap_err();
+        assert_eq!(vec![TowerError::DanglingReference { parent: "a".into(), child: "c".into() }], errors);
     }
 
-    None
+    #[test]
+    fn multiple_parents() {
+        let errors = tower("r (1) -> a, b\na (1) -> c\nb (1) -> c\nc (1)").unwrap_err();
+        assert_eq!(vec![TowerError::MultipleParents { child: "c".into(), parents: names(&["a", "b"]) }], errors);
+    }
+
+    #[test]
+    fn multiple_roots() {
+        let errors = tower("a (1) -> b\nb (1)\nc (1)").unwrap_err();
+        assert_eq!(vec![TowerError::MultipleRoots(names(&["a", "c"]))], errors);
+    }
+
+    #[test]
+    fn cycle() {
+        let errors = tower("r (1) -> a\na (1) -> b\nb (1) -> c\nc (1) -> a").unwrap_err();
+        assert_eq!(vec![TowerError::MultipleParents { child: "a".into(), parents: names(&["c", "r"]) },
+                        TowerError::Cycle(names(&["a", "b", "c"]))],
+                   errors);
+
+        let errors = tower("a (1) -> a").unwrap_err();
+        assert_eq!(vec![TowerError::Cycle(names(&["a"]))], errors);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let errors = tower("a (1) -> b\nb (1)\nb (2)").unwrap_err();
+        assert_eq!(vec![TowerError::DuplicateName("b".into())], errors);
+    }
+
+    #[test]
+    fn malformed() {
+        let errors = tower("a (1) -> b\nb 1\n\nc (99999999999999999999)").unwrap_err();
+        assert_eq!(vec![TowerError::Malformed { line: 2, text: "b 1".into() },
+                        TowerError::Malformed { line: 4, text: "c (99999999999999999999)".into() }],
+                   errors);
+        assert_eq!("line 2: cannot parse \"b 1\"", errors[0].to_string());
+    }
 }
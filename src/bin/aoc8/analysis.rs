@@ -0,0 +1,645 @@
+//! Look for mistakes in a program without running it: registers which are only
+//! read or only written, conditions which can never be true and instructions
+//! which don't do anything.
+//!
+//! Whether a condition can be true comes from working out the range of values
+//! each register can hold before each instruction. Loops make the ranges grow
+//! until they stop changing, and if they keep growing we give up and let them
+//! go all the way to the limits of an `i64`.
+
+use std::collections::BTreeSet;
+
+use super::compile::{self, Compiled};
+use super::{Action, Condition, Instruction, Operand, Operation, Operator, Target};
+
+/// Something which looks wrong with a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// The register is always zero
+    ReadNeverWritten { line: usize, register: String },
+    /// Nothing looks at the value of the register
+    WrittenNeverRead { line: usize, register: String },
+    /// The condition can never be true so the instruction never does anything
+    NeverTrue { line: usize },
+    /// The instruction leaves the register as it was
+    NoEffect { line: usize },
+    /// The instruction on `other` undoes what this one did
+    CancelsOut { line: usize, other: usize },
+}
+
+impl Warning {
+    pub fn line(&self) -> usize {
+        match *self {
+            Warning::ReadNeverWritten { line, .. } |
+            Warning::WrittenNeverRead { line, .. } |
+            Warning::NeverTrue { line } |
+            Warning::NoEffect { line } |
+            Warning::CancelsOut { line, .. } => line,
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Warning::ReadNeverWritten { line, ref register } => {
+                write!(f, "line {}: register {} is read but never written", line, register)
+            }
+            Warning::WrittenNeverRead { line, ref register } => {
+                write!(f, "line {}: register {} is written but never read", line, register)
+            }
+            Warning::NeverTrue { line } => write!(f, "line {}: condition can never be true", line),
+            Warning::NoEffect { line } => write!(f, "line {}: instruction has no effect", line),
+            Warning::CancelsOut { line, other } => {
+                write!(f, "line {}: undone by the instruction on line {}", line, other)
+            }
+        }
+    }
+}
+
+/// Check a program, returning the warnings in the order of their lines
+pub fn analyse(program: &[Instruction]) -> Vec<Warning> {
+    let compiled = compile::compile(program);
+    let mut warnings = usage(&compiled);
+    warnings.extend(never_true(&compiled));
+    warnings.extend(no_effect(&compiled.program));
+    warnings.extend(cancels_out(&compiled.program));
+    warnings.sort_by_key(|w| w.line());
+    warnings
+}
+
+/// The registers an operand reads
+fn operand_reads(o: &Operand<usize>, out: &mut Vec<usize>) {
+    if let Operand::Register(r) = *o {
+        out.push(r);
+    }
+}
+
+/// The registers a condition reads
+fn condition_reads(c: &Condition<usize>, out: &mut Vec<usize>) {
+    match *c {
+        Condition::Compare { reg, ref value, .. } => {
+            out.push(reg);
+            operand_reads(value, out);
+        }
+        Condition::And(ref a, ref b) | Condition::Or(ref a, ref b) => {
+            condition_reads(a, out);
+            condition_reads(b, out);
+        }
+        Condition::Not(ref a) => condition_reads(a, out),
+    }
+}
+
+/// The operand of an operation
+fn operand(op: &Operation<usize>) -> &Operand<usize> {
+    match *op {
+        Operation::Inc(ref o) | Operation::Dec(ref o) | Operation::Mul(ref o) |
+        Operation::Div(ref o) | Operation::Mod(ref o) | Operation::Set(ref o) => o,
+    }
+}
+
+/// The registers an instruction reads. Updates other than `set` read the
+/// register they write to as well.
+fn reads(i: &Instruction<usize>) -> Vec<usize> {
+    let mut out = Vec::new();
+    if let Some(ref c) = i.cond {
+        condition_reads(c, &mut out);
+    }
+    if let Action::Update { register, ref op } = i.action {
+        if !matches!(*op, Operation::Set(_)) {
+            out.push(register);
+        }
+        operand_reads(operand(op), &mut out);
+    }
+    out
+}
+
+/// The register an instruction writes to
+fn writes(i: &Instruction<usize>) -> Option<usize> {
+    match i.action {
+        Action::Update { register, .. } => Some(register),
+        Action::Jump(_) => None,
+    }
+}
+
+/// Registers which are only ever read or only ever written. An update like
+/// `a inc 1` doesn't count as reading `a` here, or a register which is only
+/// ever incremented would look like it's being used.
+fn usage(compiled: &Compiled) -> Vec<Warning> {
+    let n = compiled.names.len();
+    let mut first_read = vec![None; n];
+    let mut first_write = vec![None; n];
+    for i in &compiled.program {
+        let mut read = Vec::new();
+        if let Some(ref c) = i.cond {
+            condition_reads(c, &mut read);
+        }
+        if let Action::Update { register, ref op } = i.action {
+            operand_reads(operand(op), &mut read);
+            first_write[register].get_or_insert(i.line);
+        }
+        for r in read {
+            first_read[r].get_or_insert(i.line);
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (slot, name) in compiled.names.iter().enumerate() {
+        match (first_read[slot], first_write[slot]) {
+            (Some(line), None) => warnings.push(Warning::ReadNeverWritten { line, register: name.clone() }),
+            (None, Some(line)) => warnings.push(Warning::WrittenNeverRead { line, register: name.clone() }),
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// The values a register can hold, from `lo` to `hi` inclusive. The limits of
+/// an `i64` stand in for there being no limit at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Interval {
+    lo: i64,
+    hi: i64,
+}
+
+impl Interval {
+    fn exactly(v: i64) -> Self {
+        Interval { lo: v, hi: v }
+    }
+
+    fn top() -> Self {
+        Interval { lo: i64::MIN, hi: i64::MAX }
+    }
+
+    fn join(self, o: Interval) -> Self {
+        Interval { lo: self.lo.min(o.lo), hi: self.hi.max(o.hi) }
+    }
+
+    /// Only the values in both, or nothing if there aren't any
+    fn meet(self, o: Interval) -> Option<Self> {
+        let i = Interval { lo: self.lo.max(o.lo), hi: self.hi.min(o.hi) };
+        if i.lo <= i.hi {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    /// The smallest interval holding all of the values
+    fn hull<I: IntoIterator<Item = i64>>(values: I) -> Self {
+        let mut values = values.into_iter();
+        let first = values.next().expect("at least one value");
+        values.fold(Interval::exactly(first), |i, v| i.join(Interval::exactly(v)))
+    }
+
+    fn add(self, o: Interval) -> Self {
+        Interval { lo: self.lo.saturating_add(o.lo), hi: self.hi.saturating_add(o.hi) }
+    }
+
+    fn sub(self, o: Interval) -> Self {
+        Interval { lo: self.lo.saturating_sub(o.hi), hi: self.hi.saturating_sub(o.lo) }
+    }
+
+    fn mul(self, o: Interval) -> Self {
+        Interval::hull(vec![self.lo.saturating_mul(o.lo), self.lo.saturating_mul(o.hi),
+                            self.hi.saturating_mul(o.lo), self.hi.saturating_mul(o.hi)])
+    }
+
+    /// Division rounds towards zero so the extremes come from the ends of the
+    /// dividend and the divisors closest to and furthest from zero. Dividing by
+    /// zero stops the program so we leave it out.
+    fn div(self, o: Interval) -> Self {
+        let mut divisors = vec![o.lo, o.hi];
+        if o.lo <= -1 && -1 <= o.hi {
+            divisors.push(-1);
+        }
+        if o.lo <= 1 && 1 <= o.hi {
+            divisors.push(1);
+        }
+        let quotients = divisors
+            .into_iter()
+            .filter(|&d| d != 0)
+            .flat_map(|d| vec![self.lo.saturating_div(d), self.hi.saturating_div(d)])
+            .collect::<Vec<_>>();
+        if quotients.is_empty() {
+            Interval::top()
+        } else {
+            Interval::hull(quotients)
+        }
+    }
+
+    /// The remainder is smaller than the divisor and has the sign of the
+    /// dividend.
+    fn rem(self, o: Interval) -> Self {
+        let m = o.lo.unsigned_abs().max(o.hi.unsigned_abs()).saturating_sub(1).min(i64::MAX as u64) as i64;
+        if self.lo >= 0 {
+            Interval { lo: 0, hi: self.hi.min(m) }
+        } else if self.hi <= 0 {
+            Interval { lo: self.lo.max(-m), hi: 0 }
+        } else {
+            Interval { lo: self.lo.max(-m), hi: self.hi.min(m) }
+        }
+    }
+
+    /// Make a bound which is still moving go all the way so loops finish
+    fn widen(self, new: Interval) -> Self {
+        Interval {
+            lo: if new.lo < self.lo { i64::MIN } else { self.lo },
+            hi: if new.hi > self.hi { i64::MAX } else { self.hi },
+        }
+    }
+}
+
+/// The range of each register before an instruction
+type State = Vec<Interval>;
+
+/// What we know about whether a condition matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Truth {
+    Never,
+    Always,
+    Maybe,
+}
+
+impl Truth {
+    fn not(self) -> Self {
+        match self {
+            Truth::Never => Truth::Always,
+            Truth::Always => Truth::Never,
+            Truth::Maybe => Truth::Maybe,
+        }
+    }
+}
+
+fn operand_range(o: &Operand<usize>, state: &State) -> Interval {
+    match *o {
+        Operand::Value(v) => Interval::exactly(v),
+        Operand::Register(r) => state[r],
+    }
+}
+
+/// Whether `a op b` holds for every pair of values, none of them, or some
+fn compare(a: Interval, op: Operator, b: Interval) -> Truth {
+    let (always, never) = match op {
+        Operator::SmallerThan => (a.hi < b.lo, a.lo >= b.hi),
+        Operator::SmallerThanOrEqualTo => (a.hi <= b.lo, a.lo > b.hi),
+        Operator::GreaterThan => (a.lo > b.hi, a.hi <= b.lo),
+        Operator::GreaterThanOrEqualTo => (a.lo >= b.hi, a.hi < b.lo),
+        Operator::EqualTo => (a.lo == a.hi && a == b, a.meet(b).is_none()),
+        Operator::NotEqualTo => (a.meet(b).is_none(), a.lo == a.hi && a == b),
+    };
+    if always {
+        Truth::Always
+    } else if never {
+        Truth::Never
+    } else {
+        Truth::Maybe
+    }
+}
+
+fn evaluate(c: &Condition<usize>, state: &State) -> Truth {
+    match *c {
+        Condition::Compare { reg, op, ref value } => compare(state[reg], op, operand_range(value, state)),
+        Condition::And(ref a, ref b) => match (evaluate(a, state), evaluate(b, state)) {
+            (Truth::Never, _) | (_, Truth::Never) => Truth::Never,
+            (Truth::Always, Truth::Always) => Truth::Always,
+            _ => Truth::Maybe,
+        },
+        Condition::Or(ref a, ref b) => match (evaluate(a, state), evaluate(b, state)) {
+            (Truth::Always, _) | (_, Truth::Always) => Truth::Always,
+            (Truth::Never, Truth::Never) => Truth::Never,
+            _ => Truth::Maybe,
+        },
+        Condition::Not(ref a) => evaluate(a, state).not(),
+    }
+}
+
+/// Narrow the ranges down to the values for which the condition is `want`, or
+/// nothing if it can't be. Only comparisons against a number narrow anything.
+fn refine(c: &Condition<usize>, state: &State, want: bool) -> Option<State> {
+    match *c {
+        Condition::Compare { reg, op, value: Operand::Value(v) } => {
+            let op = if want { op } else { negate(op) };
+            let allowed = match op {
+                Operator::SmallerThan => Interval { lo: i64::MIN, hi: v.checked_sub(1)? },
+                Operator::SmallerThanOrEqualTo => Interval { lo: i64::MIN, hi: v },
+                Operator::GreaterThan => Interval { lo: v.checked_add(1)?, hi: i64::MAX },
+                Operator::GreaterThanOrEqualTo => Interval { lo: v, hi: i64::MAX },
+                Operator::EqualTo => Interval::exactly(v),
+                // We can only take off a value at either end
+                Operator::NotEqualTo => {
+                    let r = state[reg];
+                    if r.lo == v && r.hi == v {
+                        return None;
+                    } else if r.lo == v {
+                        Interval { lo: v + 1, hi: i64::MAX }
+                    } else if r.hi == v {
+                        Interval { lo: i64::MIN, hi: v - 1 }
+                    } else {
+                        Interval::top()
+                    }
+                }
+            };
+            let mut state = state.clone();
+            state[reg] = state[reg].meet(allowed)?;
+            Some(state)
+        }
+        Condition::Compare { .. } => {
+            if evaluate(c, state) == if want { Truth::Never } else { Truth::Always } {
+                None
+            } else {
+                Some(state.clone())
+            }
+        }
+        Condition::And(ref a, ref b) if want => refine(b, &refine(a, state, true)?, true),
+        Condition::Or(ref a, ref b) if !want => refine(b, &refine(a, state, false)?, false),
+        // Either side might be the one which decides, so take both
+        Condition::And(ref a, ref b) | Condition::Or(ref a, ref b) => {
+            match (refine(a, state, want), refine(b, state, want)) {
+                (Some(x), Some(y)) => Some(join(&x, &y)),
+                (x, y) => x.or(y),
+            }
+        }
+        Condition::Not(ref a) => refine(a, state, !want),
+    }
+}
+
+/// The comparison which is true whenever `op` is false
+fn negate(op: Operator) -> Operator {
+    match op {
+        Operator::GreaterThan => Operator::SmallerThanOrEqualTo,
+        Operator::GreaterThanOrEqualTo => Operator::SmallerThan,
+        Operator::SmallerThan => Operator::GreaterThanOrEqualTo,
+        Operator::SmallerThanOrEqualTo => Operator::GreaterThan,
+        Operator::EqualTo => Operator::NotEqualTo,
+        Operator::NotEqualTo => Operator::EqualTo,
+    }
+}
+
+fn join(a: &State, b: &State) -> State {
+    a.iter().zip(b).map(|(x, y)| x.join(*y)).collect()
+}
+
+/// Run an update on the ranges
+fn update(register: usize, op: &Operation<usize>, state: &mut State) {
+    let v = state[register];
+    state[register] = match *op {
+        Operation::Inc(ref o) => v.add(operand_range(o, state)),
+        Operation::Dec(ref o) => v.sub(operand_range(o, state)),
+        Operation::Mul(ref o) => v.mul(operand_range(o, state)),
+        Operation::Div(ref o) => v.div(operand_range(o, state)),
+        Operation::Mod(ref o) => v.rem(operand_range(o, state)),
+        Operation::Set(ref o) => operand_range(o, state),
+    };
+}
+
+/// The index of the instruction a jump goes to, if it's inside the program
+fn jump_target(target: Target, pc: usize, len: usize) -> Option<usize> {
+    let t = match target {
+        Target::Absolute(t) => t as i64,
+        Target::Relative(offset) => (pc as i64).checked_add(offset)?,
+    };
+    if t >= 0 && (t as usize) < len {
+        Some(t as usize)
+    } else {
+        None
+    }
+}
+
+/// How many times we let the ranges at an instruction grow before widening
+const WIDEN_AFTER: usize = 3;
+
+/// Work out the range of every register before each instruction. Instructions
+/// we can't reach have no state.
+fn ranges(program: &[Instruction<usize>], nregs: usize) -> Vec<Option<State>> {
+    let mut states: Vec<Option<State>> = vec![None; program.len()];
+    let mut visits = vec![0; program.len()];
+    if program.is_empty() {
+        return states;
+    }
+    states[0] = Some(vec![Interval::exactly(0); nregs]);
+
+    let mut pending = BTreeSet::new();
+    pending.insert(0);
+    while let Some(pc) = pending.iter().next().cloned() {
+        pending.remove(&pc);
+        let state = states[pc].clone().expect("pending instructions have a state");
+        let i = &program[pc];
+
+        let (taken, skipped) = match i.cond {
+            Some(ref c) => (refine(c, &state, true), refine(c, &state, false)),
+            None => (Some(state), None),
+        };
+
+        // Where we might go next and what the ranges would be
+        let mut next = Vec::new();
+        if let Some(s) = skipped {
+            next.push((pc + 1, s));
+        }
+        if let Some(mut s) = taken {
+            match i.action {
+                Action::Update { register, ref op } => {
+                    update(register, op, &mut s);
+                    next.push((pc + 1, s));
+                }
+                Action::Jump(target) => {
+                    if let Some(t) = jump_target(target, pc, program.len()) {
+                        next.push((t, s));
+                    }
+                }
+            }
+        }
+
+        for (to, s) in next {
+            if to >= program.len() {
+                continue;
+            }
+            let merged = match states[to] {
+                None => s,
+                Some(ref old) => {
+                    let joined = join(old, &s);
+                    if joined == *old {
+                        continue;
+                    }
+                    visits[to] += 1;
+                    if visits[to] > WIDEN_AFTER {
+                        old.iter().zip(&joined).map(|(o, n)| o.widen(*n)).collect()
+                    } else {
+                        joined
+                    }
+                }
+            };
+            states[to] = Some(merged);
+            pending.insert(to);
+        }
+    }
+
+    states
+}
+
+/// Instructions we can reach whose condition can't match where they are
+fn never_true(compiled: &Compiled) -> Vec<Warning> {
+    let states = ranges(&compiled.program, compiled.names.len());
+    compiled.program
+        .iter()
+        .zip(states)
+        .filter_map(|(i, state)| match (&i.cond, state) {
+            (Some(c), Some(state)) if evaluate(c, &state) == Truth::Never => Some(Warning::NeverTrue { line: i.line }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Updates which leave the register as it was, like `a inc 0` or `a mul 1`
+fn no_effect(program: &[Instruction<usize>]) -> Vec<Warning> {
+    program
+        .iter()
+        .filter(|i| match i.action {
+            Action::Update { register, ref op } => match *op {
+                Operation::Inc(Operand::Value(0)) | Operation::Dec(Operand::Value(0)) |
+                Operation::Mul(Operand::Value(1)) | Operation::Div(Operand::Value(1)) => true,
+                Operation::Set(Operand::Register(r)) => r == register,
+                _ => false,
+            },
+            Action::Jump(_) => false,
+        })
+        .map(|i| Warning::NoEffect { line: i.line })
+        .collect()
+}
+
+/// How much an update adds to its register, if it's a fixed amount
+fn step(op: &Operation<usize>) -> Option<i64> {
+    match *op {
+        Operation::Inc(Operand::Value(v)) => Some(v),
+        Operation::Dec(Operand::Value(v)) => v.checked_neg(),
+        _ => None,
+    }
+}
+
+/// Pairs of updates like `a inc 5 if b > 1` and `a dec 5 if b > 1` where
+/// nothing in between can see the difference or change the condition
+fn cancels_out(program: &[Instruction<usize>]) -> Vec<Warning> {
+    let targets = program
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, i)| match i.action {
+            Action::Jump(t) => jump_target(t, pc, program.len()),
+            Action::Update { .. } => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut warnings = Vec::new();
+    for (pc, i) in program.iter().enumerate() {
+        let (register, amount) = match i.action {
+            Action::Update { register, ref op } => match step(op) {
+                Some(amount) if amount != 0 => (register, amount),
+                _ => continue,
+            },
+            Action::Jump(_) => continue,
+        };
+        let mut cond_reads = Vec::new();
+        if let Some(ref c) = i.cond {
+            condition_reads(c, &mut cond_reads);
+        }
+        // If the condition looks at the register, the first update changes
+        // whether the second one happens.
+        if cond_reads.contains(&register) {
+            continue;
+        }
+
+        for (j, other) in program.iter().enumerate().skip(pc + 1) {
+            if targets.contains(&j) {
+                break;
+            }
+            if let Action::Update { register: r, ref op } = other.action {
+                if r == register && other.cond == i.cond && step(op) == amount.checked_neg() {
+                    warnings.push(Warning::CancelsOut { line: i.line, other: other.line });
+                    break;
+                }
+            }
+            let w = writes(other);
+            let touched = match w {
+                None => true,
+                Some(w) => w == register || cond_reads.contains(&w) || reads(other).contains(&register),
+            };
+            if touched {
+                break;
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod test {
+    use aoc2017::rng::XorShift;
+    use super::{analyse, Warning};
+    use super::super::{bench, parser, Machine, DEFAULT_STEP_LIMIT};
+
+    fn check(program: &str) -> Vec<String> {
+        analyse(&parser::parse(program).unwrap()).iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn sample_input() {
+        assert_eq!(vec!["line 1: condition can never be true"],
+                   check(include_str!("../../../input8-sample.txt")));
+    }
+
+    #[test]
+    fn usage() {
+        let warnings = analyse(&parser::parse("a inc 1 if b > 0\nc set a\na inc c").unwrap());
+        assert_eq!(vec![Warning::ReadNeverWritten { line: 1, register: "b".into() },
+                        Warning::NeverTrue { line: 1 }],
+                   warnings);
+    }
+
+    #[test]
+    fn intervals() {
+        // The loop leaves a at 10 or more and b at 0 or more
+        assert_eq!(vec!["line 5: condition can never be true",
+                        "line 6: condition can never be true"],
+                   check("loop:\na inc 1\nb inc a\njmp loop if a < 10\n\
+                          c inc 1 if a < 10 or b < 0\nc dec 1 if not a >= 10\nc inc 1 if b > 100\nc set 1 if c > 0"));
+        assert_eq!(vec!["line 3: condition can never be true"],
+                   check("a set 7\na mod 4\nb inc a if a > 3\nb inc 1 if b < 1"));
+    }
+
+    #[test]
+    fn cancelling() {
+        assert_eq!(vec!["line 1: undone by the instruction on line 3",
+                        "line 4: instruction has no effect",
+                        "line 5: instruction has no effect"],
+                   check("a inc 5 if b < 1\nc inc b\na dec 5 if b < 1\nb mul 1\nb set b\nb inc 1 if c < 1\nc dec b\nb dec 1\nc inc a"));
+        // Something looks at the register in between, so it's not the same
+        assert!(!check("a inc 5\nb inc 1 if a > 2\na dec 5\nb inc a").iter().any(|w| w.contains("undone")));
+        // A jump could come in between the two
+        assert!(!check("a inc 5\nx:\na dec 5\nb inc a\njmp x if b < 0").iter().any(|w| w.contains("undone")));
+        // A jump too far to work out where it lands goes nowhere in the program
+        assert_eq!(vec!["line 1: register a is written but never read"],
+                   check("a inc 1\njmp 9223372036854775807\na dec 1"));
+    }
+
+    #[test]
+    fn never_true_is_never_wrong() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20 {
+            let insns = parser::parse(&bench::generate(&mut rng, 100, 4, 5)).unwrap();
+            let never = analyse(&insns)
+                .into_iter()
+                .filter_map(|w| match w {
+                    Warning::NeverTrue { line } => Some(line),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let mut m = Machine::new(&insns, DEFAULT_STEP_LIMIT);
+            while !m.finished() {
+                let line = insns[m.pc].line;
+                if m.step().unwrap().matched == Some(true) {
+                    assert!(!never.contains(&line), "line {} matched", line);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Generate large programs and time how long the interpreter takes to run them
+//! with named registers and with compiled slots.
+
+use std::time::{Duration, Instant};
+
+use aoc2017::rng::XorShift;
+use super::{compile, parser, Machine, DEFAULT_STEP_LIMIT};
+
+const OPERATORS: [&str; 6] = ["<", ">", "<=", ">=", "==", "!="];
+
+/// Generate a program of `size` random instructions in the style of the puzzle
+/// input over `nregs` registers, which runs `rounds` times through a loop.
+pub fn generate(rng: &mut XorShift, size: usize, nregs: usize, rounds: usize) -> String {
+    let mut program = String::new();
+    for _ in 0..size {
+        let reg = rng.next(nregs as u64);
+        let op = if rng.next(2) == 0 { "inc" } else { "dec" };
+        let amount = rng.next(2000) as i64 - 1000;
+        let cond_reg = rng.next(nregs as u64);
+        let cond_op = OPERATORS[rng.next(OPERATORS.len() as u64) as usize];
+        let cond_value = rng.next(2000) as i64 - 1000;
+        program.push_str(&format!("r{} {} {} if r{} {} {}\n", reg, op, amount, cond_reg, cond_op, cond_value));
+    }
+    program.push_str(&format!("round inc 1\njmp -{} if round < {}\n", size + 1, rounds));
+    program
+}
+
+/// Run the program both ways, returning how long each one took
+fn time(program: &str) -> (Duration, Duration) {
+    let insns = parser::parse(program).expect("generated program parses");
+
+    let start = Instant::now();
+    let mut m = Machine::new(&insns, DEFAULT_STEP_LIMIT);
+    while !m.finished() {
+        m.step().expect("run interpreted");
+    }
+    let interpreted = start.elapsed();
+
+    let start = Instant::now();
+    let compiled = compile::compile(&insns);
+    let mut m = Machine::with_registers(&compiled.program, compiled.registers(), DEFAULT_STEP_LIMIT);
+    while !m.finished() {
+        m.step().expect("run compiled");
+    }
+    let slots = start.elapsed();
+
+    (interpreted, slots)
+}
+
+/// Time programs of increasing size up to `size` instructions
+pub fn bench(size: usize) {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let mut n = 1000;
+    loop {
+        let n_now = n.min(size);
+        // Keep the number of steps around the same for every size
+        let rounds = (1_000_000 / n_now).max(1);
+        let program = generate(&mut rng, n_now, 26, rounds);
+        let (interpreted, slots) = time(&program);
+        println!("{:8} instructions x {:5} rounds: names {:8.1?}, slots {:8.1?} ({:.1}x)",
+                 n_now, rounds, interpreted, slots,
+                 interpreted.as_secs_f64() / slots.as_secs_f64());
+        if n_now == size {
+            break;
+        }
+        n *= 10;
+    }
+}
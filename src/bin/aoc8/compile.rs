@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use super::{Action, Condition, Instruction, Operand, Operation, RegisterFile};
+
+/// A program whose registers have been turned into indices into a list, so
+/// running it doesn't need to hash any names.
+#[derive(Debug)]
+pub struct Compiled {
+    pub program: Vec<Instruction<usize>>,
+    /// The name of each register, indexed by its slot
+    pub names: Vec<String>,
+}
+
+impl Compiled {
+    /// A fresh set of registers for running the program, all set to zero
+    pub fn registers(&self) -> Slots<'_> {
+        Slots {
+            values: vec![0; self.names.len()],
+            written: vec![false; self.names.len()],
+            names: &self.names,
+        }
+    }
+}
+
+/// The registers of a compiled program
+#[derive(Debug, Clone)]
+pub struct Slots<'a> {
+    pub values: Vec<i64>,
+    /// Whether each register has been set. Every register the program mentions
+    /// has a slot, but one which is only ever read doesn't really exist.
+    pub written: Vec<bool>,
+    pub names: &'a [String],
+}
+
+impl<'a> Slots<'a> {
+    /// The registers which have been set along with their values
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, i64)> + '_ {
+        let names = self.names;
+        self.values
+            .iter()
+            .zip(&self.written)
+            .enumerate()
+            .filter(|&(_, (_, &written))| written)
+            .map(move |(i, (&v, _))| (names[i].as_str(), v))
+    }
+
+    /// The register with the largest value, if any have been set
+    pub fn largest(&self) -> Option<(&'a str, i64)> {
+        self.iter().max_by_key(|&(_, v)| v)
+    }
+}
+
+impl<'a> RegisterFile for Slots<'a> {
+    type Reg = usize;
+
+    fn get(&self, r: &usize) -> i64 {
+        self.values[*r]
+    }
+
+    fn set(&mut self, r: &usize, v: i64) {
+        self.values[*r] = v;
+        self.written[*r] = true;
+    }
+
+    fn name(&self, r: &usize) -> String {
+        self.names[*r].clone()
+    }
+}
+
+/// Hands out a slot for each register name the first time we see it
+#[derive(Default)]
+struct Interner {
+    slots: HashMap<String, usize>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let slot = self.names.len();
+        self.slots.insert(name.to_string(), slot);
+        self.names.push(name.to_string());
+        slot
+    }
+
+    fn operand(&mut self, o: &Operand) -> Operand<usize> {
+        match *o {
+            Operand::Value(v) => Operand::Value(v),
+            Operand::Register(ref r) => Operand::Register(self.intern(r)),
+        }
+    }
+
+    fn operation(&mut self, op: &Operation) -> Operation<usize> {
+        match *op {
+            Operation::Inc(ref o) => Operation::Inc(self.operand(o)),
+            Operation::Dec(ref o) => Operation::Dec(self.operand(o)),
+            Operation::Mul(ref o) => Operation::Mul(self.operand(o)),
+            Operation::Div(ref o) => Operation::Div(self.operand(o)),
+            Operation::Mod(ref o) => Operation::Mod(self.operand(o)),
+            Operation::Set(ref o) => Operation::Set(self.operand(o)),
+        }
+    }
+
+    fn condition(&mut self, c: &Condition) -> Condition<usize> {
+        match *c {
+            Condition::Compare { ref reg, op, ref value } => Condition::Compare {
+                reg: self.intern(reg),
+                op,
+                value: self.operand(value),
+            },
+            Condition::And(ref a, ref b) => Condition::And(Box::new(self.condition(a)), Box::new(self.condition(b))),
+            Condition::Or(ref a, ref b) => Condition::Or(Box::new(self.condition(a)), Box::new(self.condition(b))),
+            Condition::Not(ref a) => Condition::Not(Box::new(self.condition(a))),
+        }
+    }
+
+    fn instruction(&mut self, i: &Instruction) -> Instruction<usize> {
+        let action = match i.action {
+            Action::Update { ref register, ref op } => Action::Update {
+                register: self.intern(register),
+                op: self.operation(op),
+            },
+            Action::Jump(target) => Action::Jump(target),
+        };
+        Instruction {
+            line: i.line,
+            action,
+            cond: i.cond.as_ref().map(|c| self.condition(c)),
+        }
+    }
+}
+
+/// Give every register in the program its own slot, in the order in which they
+/// first appear.
+pub fn compile(program: &[Instruction]) -> Compiled {
+    let mut interner = Interner::default();
+    let program = program.iter().map(|i| interner.instruction(i)).collect();
+    Compiled {
+        program,
+        names: interner.names,
+    }
+}
@@ -0,0 +1,292 @@
+//! An interactive debugger for the register machine. It can stop at an
+//! instruction or when a condition on the registers becomes true, and run the
+//! program one instruction at a time showing what each one did.
+
+use std::io::{BufRead, Write};
+use super::{condition_matches, Condition, Control, Effect, Hook, Instruction, Machine, Registers, RuntimeError};
+use super::parser;
+
+/// Stop before running a particular instruction
+struct Breakpoint {
+    index: usize,
+}
+
+impl Hook for Breakpoint {
+    fn before(&mut self, m: &Machine) -> Control {
+        if m.pc == self.index {
+            Control::Break
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+/// Stop when a condition on the registers goes from false to true
+struct ConditionBreakpoint {
+    cond: Condition,
+    was_true: bool,
+}
+
+impl Hook for ConditionBreakpoint {
+    fn after(&mut self, m: &Machine, _pc: usize, _effect: &Effect) -> Control {
+        let is_true = condition_matches(&self.cond, &m.regs);
+        let was_true = std::mem::replace(&mut self.was_true, is_true);
+        if is_true && !was_true {
+            Control::Break
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+/// Log every write to a register
+struct Watchpoint<W> {
+    register: String,
+    out: W,
+}
+
+impl<W: Write> Hook for Watchpoint<W> {
+    fn after(&mut self, m: &Machine, pc: usize, effect: &Effect) -> Control {
+        if let Some((register, old, new)) = effect.write {
+            if *register == self.register {
+                writeln!(self.out, "line {}: {} {} -> {}", m.program[pc].line, register, old, new)
+                    .expect("write watchpoint");
+            }
+        }
+        Control::Continue
+    }
+}
+
+/// Every comparison in the condition along with whether it's true
+fn explain(cond: &Condition, regs: &Registers) -> Vec<String> {
+    match *cond {
+        Condition::Compare { ref reg, .. } => {
+            let v = regs.get(reg).cloned().unwrap_or(0);
+            let res = if condition_matches(cond, regs) { "passed" } else { "failed" };
+            vec![format!("{} ({} is {}): {}", cond, reg, v, res)]
+        }
+        Condition::And(ref a, ref b) | Condition::Or(ref a, ref b) => {
+            let mut v = explain(a, regs);
+            v.extend(explain(b, regs));
+            v
+        }
+        Condition::Not(ref a) => explain(a, regs),
+    }
+}
+
+fn print_regs(regs: &Registers) {
+    let mut names = regs.keys().collect::<Vec<&String>>();
+    names.sort();
+    for name in names {
+        println!("  {} = {}", name, regs[name]);
+    }
+}
+
+fn print_next(m: &Machine) {
+    if m.finished() {
+        println!("The program has finished");
+        return;
+    }
+
+    let i = &m.program[m.pc];
+    println!("next: [{}] line {}: {}", m.pc, i.line, i);
+}
+
+/// Show what the instruction we just ran did. The registers have already
+/// changed, so we explain the condition with the values from before.
+fn print_effect(i: &Instruction, before: &Registers, effect: &Effect) {
+    println!("ran line {}: {}", i.line, i);
+    if let Some(matched) = effect.matched {
+        println!("  condition {}", if matched { "passed" } else { "failed" });
+    }
+    if let Some(ref cond) = i.cond {
+        for e in explain(cond, before) {
+            println!("  {}", e);
+        }
+    }
+    if let Some((register, old, new)) = effect.write {
+        println!("  {} {} -> {}", register, old, new);
+    }
+}
+
+/// Run the next instruction on its own. The hooks see it just as they would
+/// in `run_with`, so a condition breakpoint knows whether it's true now.
+fn step_with<'a>(m: &mut Machine<'a>, hooks: &mut [&mut dyn Hook]) -> Result<Effect<'a>, RuntimeError> {
+    let pc = m.pc;
+    let effect = m.step()?;
+    for h in hooks.iter_mut() {
+        h.after(m, pc, &effect);
+    }
+    Ok(effect)
+}
+
+const HELP: &str = "\
+step, s            run the next instruction
+continue, c        run until a breakpoint or the end
+break N            stop before running instruction N
+break if COND      stop when COND becomes true, e.g. `break if a > 1000`
+watch REG          log every write to REG
+delete             remove all breakpoints and watchpoints
+regs               show every register
+where              show the next instruction
+quit               exit";
+
+/// Read debugger commands from stdin and run the program as they say
+pub fn debug(program: &[Instruction], step_limit: usize) {
+    let mut m = Machine::new(program, step_limit);
+    let mut breakpoints: Vec<Breakpoint> = Vec::new();
+    let mut conditions: Vec<ConditionBreakpoint> = Vec::new();
+    let mut watchpoints: Vec<Watchpoint<std::io::Stdout>> = Vec::new();
+
+    print_next(&m);
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut line = String::new();
+    loop {
+        write!(stdout, "(debug) ").expect("prompt");
+        stdout.flush().expect("flush");
+
+        line.clear();
+        if stdin.lock().read_line(&mut line).expect("stdin") == 0 {
+            break;
+        }
+
+        let cmd = line.trim();
+        let (word, rest) = match cmd.find(' ') {
+            Some(pos) => (&cmd[..pos], cmd[pos..].trim()),
+            None => (cmd, ""),
+        };
+
+        match word {
+            "" => {}
+            "quit" | "q" => break,
+            "step" | "s" => {
+                if m.finished() {
+                    println!("The program has finished");
+                    continue;
+                }
+
+                let mut hooks: Vec<&mut dyn Hook> = Vec::new();
+                hooks.extend(conditions.iter_mut().map(|h| h as &mut dyn Hook));
+                hooks.extend(watchpoints.iter_mut().map(|h| h as &mut dyn Hook));
+
+                let before = m.regs.clone();
+                let pc = m.pc;
+                match step_with(&mut m, &mut hooks) {
+                    Ok(effect) => {
+                        print_effect(&program[pc], &before, &effect);
+                        print_next(&m);
+                    }
+                    Err(e) => println!("error: {}", e),
+                }
+            }
+            "continue" | "c" => {
+                let mut hooks: Vec<&mut dyn Hook> = Vec::new();
+                hooks.extend(breakpoints.iter_mut().map(|h| h as &mut dyn Hook));
+                hooks.extend(conditions.iter_mut().map(|h| h as &mut dyn Hook));
+                hooks.extend(watchpoints.iter_mut().map(|h| h as &mut dyn Hook));
+
+                match m.run_with(&mut hooks) {
+                    Ok(_) => print_next(&m),
+                    Err(e) => println!("error: {}", e),
+                }
+            }
+            "break" if rest.starts_with("if ") => {
+                match parser::parse_condition(1, &rest[3..]) {
+                    Ok(cond) => {
+                        let was_true = condition_matches(&cond, &m.regs);
+                        conditions.push(ConditionBreakpoint { cond, was_true });
+                    }
+                    Err(e) => println!("error: {}", e),
+                }
+            }
+            "break" => match rest.parse() {
+                Ok(index) if index < program.len() => breakpoints.push(Breakpoint { index }),
+                _ => println!("error: expected an instruction index below {}", program.len()),
+            },
+            "watch" if !rest.is_empty() => watchpoints.push(Watchpoint {
+                register: rest.to_owned(),
+                out: std::io::stdout(),
+            }),
+            "delete" => {
+                breakpoints.clear();
+                conditions.clear();
+                watchpoints.clear();
+            }
+            "regs" => print_regs(&m.regs),
+            "where" => print_next(&m),
+            _ => println!("{}", HELP),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{step_with, Breakpoint, ConditionBreakpoint, Watchpoint};
+    use super::super::{parser, Hook, Machine, DEFAULT_STEP_LIMIT};
+
+    const PROGRAM: &str = "a set 0
+                           loop:
+                           a inc 3
+                           b inc 1 if a > 7
+                           jmp loop if a < 12";
+
+    #[test]
+    fn breakpoints() {
+        let program = parser::parse(PROGRAM).unwrap();
+        let mut m = Machine::new(&program, DEFAULT_STEP_LIMIT);
+        let mut b = Breakpoint { index: 2 };
+        let mut cond = ConditionBreakpoint {
+            cond: parser::parse_condition(1, "b > 0").unwrap(),
+            was_true: false,
+        };
+
+        assert_eq!(Ok(true), m.run_with(&mut [&mut b as &mut dyn Hook]));
+        assert_eq!(2, m.pc);
+        assert_eq!(3, m.regs["a"]);
+
+        // Carrying on goes past the breakpoint we're stopped at
+        assert_eq!(Ok(true), m.run_with(&mut [&mut b as &mut dyn Hook]));
+        assert_eq!(2, m.pc);
+        assert_eq!(6, m.regs["a"]);
+
+        assert_eq!(Ok(true), m.run_with(&mut [&mut cond as &mut dyn Hook]));
+        assert_eq!(3, m.pc);
+        assert_eq!(Some(&1), m.regs.get("b"));
+
+        assert_eq!(Ok(false), m.run_with(&mut [&mut cond as &mut dyn Hook]));
+        assert_eq!(2, m.regs["b"]);
+    }
+
+    #[test]
+    fn stepping_keeps_conditions_up_to_date() {
+        let program = parser::parse(PROGRAM).unwrap();
+        let mut m = Machine::new(&program, DEFAULT_STEP_LIMIT);
+        let mut cond = ConditionBreakpoint {
+            cond: parser::parse_condition(1, "b > 0").unwrap(),
+            was_true: false,
+        };
+
+        // b first gets set by the ninth instruction we run
+        for _ in 0..9 {
+            step_with(&mut m, &mut [&mut cond as &mut dyn Hook]).unwrap();
+        }
+        assert!(cond.was_true);
+
+        // It stays true from here on, so there's nothing to stop for
+        assert_eq!(Ok(false), m.run_with(&mut [&mut cond as &mut dyn Hook]));
+    }
+
+    #[test]
+    fn watchpoint() {
+        let program = parser::parse(PROGRAM).unwrap();
+        let mut m = Machine::new(&program, DEFAULT_STEP_LIMIT);
+        let mut w = Watchpoint { register: "a".into(), out: Vec::new() };
+
+        assert_eq!(Ok(false), m.run_with(&mut [&mut w as &mut dyn Hook]));
+        assert_eq!("line 1: a 0 -> 0\nline 3: a 0 -> 3\nline 3: a 3 -> 6\n\
+                    line 3: a 6 -> 9\nline 3: a 9 -> 12\n",
+                   String::from_utf8(w.out).unwrap());
+    }
+}
@@ -0,0 +1,108 @@
+//! Keep track of the smallest and largest values each register held while the
+//! program ran, and when each new record was set.
+
+/// A value a register reached when running the instruction at `pc`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub value: i64,
+    /// The index of the instruction which wrote the value
+    pub pc: usize,
+    /// How many instructions had run before this one
+    pub step: usize,
+}
+
+/// Every time a register went lower or higher than it had been before. The
+/// first write counts as both.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct History {
+    pub lows: Vec<Record>,
+    pub highs: Vec<Record>,
+}
+
+impl History {
+    pub fn min(&self) -> Option<Record> {
+        self.lows.last().cloned()
+    }
+
+    pub fn max(&self) -> Option<Record> {
+        self.highs.last().cloned()
+    }
+}
+
+/// The history of each register slot in a compiled program
+#[derive(Debug)]
+pub struct Extremes {
+    pub registers: Vec<History>,
+    /// The slot and record of the highest value written to any register
+    peak: Option<(usize, Record)>,
+}
+
+impl Extremes {
+    pub fn new(slots: usize) -> Self {
+        Extremes {
+            registers: vec![History::default(); slots],
+            peak: None,
+        }
+    }
+
+    /// Note that the instruction at `pc` wrote `value` into `slot`
+    pub fn record(&mut self, slot: usize, value: i64, pc: usize, step: usize) {
+        let record = Record { value, pc, step };
+        let h = &mut self.registers[slot];
+        if h.min().is_none_or(|r| value < r.value) {
+            h.lows.push(record);
+        }
+        if h.max().is_none_or(|r| value > r.value) {
+            h.highs.push(record);
+        }
+        if self.peak.is_none_or(|(_, r)| value > r.value) {
+            self.peak = Some((slot, record));
+        }
+    }
+
+    /// The highest value any register held, which register it was in and the
+    /// index of the instruction which wrote it. Nothing if no register was
+    /// ever written.
+    pub fn peak<'a>(&self, names: &'a [String]) -> Option<(&'a str, i64, usize)> {
+        self.peak.map(|(slot, r)| (names[slot].as_str(), r.value, r.pc))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Extremes, Record};
+    use super::super::{compile, parser, Machine, DEFAULT_STEP_LIMIT};
+
+    fn run(program: &str) -> (Vec<String>, Extremes) {
+        let insns = parser::parse(program).unwrap();
+        let compiled = compile::compile(&insns);
+        let mut extremes = Extremes::new(compiled.names.len());
+        let mut m = Machine::with_registers(&compiled.program, compiled.registers(), DEFAULT_STEP_LIMIT);
+        while !m.finished() {
+            let (pc, step) = (m.pc, m.steps);
+            if let Some((&slot, _, v)) = m.step().unwrap().write {
+                extremes.record(slot, v, pc, step);
+            }
+        }
+        (compiled.names, extremes)
+    }
+
+    #[test]
+    fn negative_peak() {
+        let (names, extremes) = run("a dec 5\nb dec 3\na inc 4\nb dec 1");
+        assert_eq!(Some(("a", -1, 2)), extremes.peak(&names));
+
+        let (names, extremes) = run("a inc 1 if a > 0");
+        assert_eq!(None, extremes.peak(&names));
+    }
+
+    #[test]
+    fn history() {
+        let (_, extremes) = run("a inc 3\nloop:\na dec 2\njmp loop if a > -2\na set 10\na set 3");
+        let h = &extremes.registers[0];
+        assert_eq!(vec![3, 1, -1, -3], h.lows.iter().map(|r| r.value).collect::<Vec<_>>());
+        assert_eq!(vec![3, 10], h.highs.iter().map(|r| r.value).collect::<Vec<_>>());
+        assert_eq!(Some(Record { value: -3, pc: 1, step: 5 }), h.min());
+        assert_eq!(Some(Record { value: 10, pc: 3, step: 7 }), h.max());
+    }
+}
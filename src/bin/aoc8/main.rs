@@ -0,0 +1,706 @@
+extern crate aoc2017;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+mod analysis;
+mod bench;
+mod compile;
+mod debugger;
+mod extremes;
+mod parser;
+mod repl;
+mod timeline;
+
+// The instructions are generic over how they refer to registers. The parser
+// gives us their names, and compiling the program turns those into slots.
+
+/// Either a number written in the program or the value of a register
+#[derive(Debug, Clone, PartialEq)]
+enum Operand<R = String> {
+    Value(i64),
+    Register(R),
+}
+
+impl<R> Operand<R> {
+    fn value<F: RegisterFile<Reg = R>>(&self, regs: &F) -> i64 {
+        match *self {
+            Operand::Value(v) => v,
+            Operand::Register(ref r) => regs.get(r),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Condition<R = String> {
+    /// Compare a register against an operand
+    Compare {
+        reg: R,
+        op: Operator,
+        value: Operand<R>,
+    },
+    And(Box<Condition<R>>, Box<Condition<R>>),
+    Or(Box<Condition<R>>, Box<Condition<R>>),
+    Not(Box<Condition<R>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    GreaterThan,
+    GreaterThanOrEqualTo,
+    SmallerThan,
+    SmallerThanOrEqualTo,
+    EqualTo,
+    NotEqualTo,
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match *self {
+            Operator::GreaterThan => ">",
+            Operator::GreaterThanOrEqualTo => ">=",
+            Operator::SmallerThan => "<",
+            Operator::SmallerThanOrEqualTo => "<=",
+            Operator::EqualTo => "==",
+            Operator::NotEqualTo => "!=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Operation<R = String> {
+    Inc(Operand<R>),
+    Dec(Operand<R>),
+    Mul(Operand<R>),
+    /// Integer division, rounding towards zero
+    Div(Operand<R>),
+    /// The remainder of `Div`, which has the same sign as the register
+    Mod(Operand<R>),
+    Set(Operand<R>),
+}
+
+/// Where a jump goes to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    /// The index of an instruction, which is what labels turn into
+    Absolute(usize),
+    /// A number of instructions forwards or backwards from the jump
+    Relative(i64),
+}
+
+#[derive(Debug, PartialEq)]
+enum Action<R = String> {
+    /// Change the value of a register
+    Update { register: R, op: Operation<R> },
+    /// Carry on from somewhere else in the program
+    Jump(Target),
+}
+
+#[derive(Debug)]
+struct Instruction<R = String> {
+    /// The line of the input the instruction comes from
+    line: usize,
+    action: Action<R>,
+    /// The instruction only does anything if this matches
+    cond: Option<Condition<R>>,
+}
+
+impl<R: std::fmt::Display> std::fmt::Display for Operand<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Operand::Value(v) => write!(f, "{}", v),
+            Operand::Register(ref r) => write!(f, "{}", r),
+        }
+    }
+}
+
+impl<R: std::fmt::Display> std::fmt::Display for Condition<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Anything but a comparison gets parentheses when it's inside another
+        // condition, which is more than needed but always reads back the same.
+        fn inner<R: std::fmt::Display>(c: &Condition<R>, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match *c {
+                Condition::Compare { .. } => write!(f, "{}", c),
+                _ => write!(f, "({})", c),
+            }
+        }
+
+        match *self {
+            Condition::Compare { ref reg, op, ref value } => write!(f, "{} {} {}", reg, op, value),
+            Condition::And(ref a, ref b) => {
+                inner(a, f)?;
+                write!(f, " and ")?;
+                inner(b, f)
+            }
+            Condition::Or(ref a, ref b) => {
+                inner(a, f)?;
+                write!(f, " or ")?;
+                inner(b, f)
+            }
+            Condition::Not(ref a) => {
+                write!(f, "not ")?;
+                inner(a, f)
+            }
+        }
+    }
+}
+
+impl<R: std::fmt::Display> std::fmt::Display for Operation<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Operation::Inc(ref d) => write!(f, "inc {}", d),
+            Operation::Dec(ref d) => write!(f, "dec {}", d),
+            Operation::Mul(ref d) => write!(f, "mul {}", d),
+            Operation::Div(ref d) => write!(f, "div {}", d),
+            Operation::Mod(ref d) => write!(f, "mod {}", d),
+            Operation::Set(ref d) => write!(f, "set {}", d),
+        }
+    }
+}
+
+impl<R: std::fmt::Display> std::fmt::Display for Instruction<R> {
+    /// Jumps to labels show the index of the instruction they go to, as we
+    /// don't keep the names of the labels around.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.action {
+            Action::Update { ref register, ref op } => write!(f, "{} {}", register, op)?,
+            Action::Jump(Target::Absolute(target)) => write!(f, "jmp to instruction {}", target)?,
+            Action::Jump(Target::Relative(offset)) => write!(f, "jmp {}", offset)?,
+        }
+
+        if let Some(ref cond) = self.cond {
+            write!(f, " if {}", cond)?;
+        }
+
+        Ok(())
+    }
+}
+
+type Registers = HashMap<String, i64>;
+
+/// Somewhere to keep the values of the registers
+trait RegisterFile {
+    /// How the instructions refer to a register
+    type Reg;
+
+    /// The value of a register, which starts off as zero
+    fn get(&self, r: &Self::Reg) -> i64;
+    fn set(&mut self, r: &Self::Reg, v: i64);
+    /// The name of a register, for error messages
+    fn name(&self, r: &Self::Reg) -> String;
+}
+
+impl RegisterFile for Registers {
+    type Reg = String;
+
+    fn get(&self, r: &String) -> i64 {
+        HashMap::get(self, r).cloned().unwrap_or(0)
+    }
+
+    fn set(&mut self, r: &String, v: i64) {
+        match self.get_mut(r) {
+            Some(old) => *old = v,
+            None => {
+                self.insert(r.clone(), v);
+            }
+        }
+    }
+
+    fn name(&self, r: &String) -> String {
+        r.clone()
+    }
+}
+
+/// What running a single instruction did
+#[derive(Debug, PartialEq)]
+struct Effect<'a, R: 'a = String> {
+    /// The index of the instruction to run next, which can be outside of the
+    /// program if it's a jump
+    next: i64,
+    /// Whether the condition matched, if there was one
+    matched: Option<bool>,
+    /// The register which was written to with its old and new values
+    write: Option<(&'a R, i64, i64)>,
+}
+
+/// Whether the machine should keep going
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Continue,
+    Break,
+}
+
+/// Something which wants to know what the machine is doing as it runs, like a
+/// breakpoint.
+trait Hook {
+    /// Called before running the instruction at `m.pc`
+    fn before(&mut self, _m: &Machine) -> Control {
+        Control::Continue
+    }
+
+    /// Called after running the instruction at `pc`
+    fn after(&mut self, _m: &Machine, _pc: usize, _effect: &Effect) -> Control {
+        Control::Continue
+    }
+}
+
+/// The ways in which running an instruction can fail
+#[derive(Debug, PartialEq)]
+enum RuntimeError {
+    /// The result doesn't fit in the register
+    Overflow { line: usize, register: String },
+    DivisionByZero { line: usize, register: String },
+    /// A jump to somewhere which is neither in the program nor just past its
+    /// end
+    JumpOutOfRange { line: usize, target: i64 },
+    /// We ran this many instructions without getting to the end
+    StepLimit(usize),
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            RuntimeError::Overflow { line, ref register } => {
+                write!(f, "line {}: {} overflowed", line, register)
+            }
+            RuntimeError::DivisionByZero { line, ref register } => {
+                write!(f, "line {}: division by zero when changing {}", line, register)
+            }
+            RuntimeError::JumpOutOfRange { line, target } => {
+                write!(f, "line {}: jump to instruction {} which is out of range", line, target)
+            }
+            RuntimeError::StepLimit(limit) => write!(f, "still running after {} steps", limit),
+        }
+    }
+}
+
+/// How many instructions we run by default before giving up
+const DEFAULT_STEP_LIMIT: usize = 10_000_000;
+
+/// Runs a program, jumping around it as the instructions say
+struct Machine<'a, F: RegisterFile + 'a = Registers> where F::Reg: 'a {
+    program: &'a [Instruction<F::Reg>],
+    regs: F,
+    /// The index of the next instruction to run
+    pc: usize,
+    /// How many instructions we've run so far
+    steps: usize,
+    step_limit: usize,
+}
+
+impl<'a> Machine<'a> {
+    fn new(program: &'a [Instruction], step_limit: usize) -> Self {
+        Self::with_registers(program, Registers::new(), step_limit)
+    }
+}
+
+impl<'a, F: RegisterFile> Machine<'a, F> {
+    fn with_registers(program: &'a [Instruction<F::Reg>], regs: F, step_limit: usize) -> Self {
+        Machine {
+            program,
+            regs,
+            pc: 0,
+            steps: 0,
+            step_limit,
+        }
+    }
+
+    /// We're done once we've gone past the last instruction
+    fn finished(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    /// Run the next instruction
+    fn step(&mut self) -> Result<Effect<'a, F::Reg>, RuntimeError> {
+        if self.steps >= self.step_limit {
+            return Err(RuntimeError::StepLimit(self.step_limit));
+        }
+
+        let program = self.program;
+        let i = &program[self.pc];
+        let effect = execute(i, self.pc, &mut self.regs)?;
+        if effect.next < 0 || effect.next > program.len() as i64 {
+            return Err(RuntimeError::JumpOutOfRange { line: i.line, target: effect.next });
+        }
+
+        self.pc = effect.next as usize;
+        self.steps += 1;
+        Ok(effect)
+    }
+}
+
+impl<'a> Machine<'a> {
+    /// Run until we get to the end or one of the hooks asks us to stop. The
+    /// first instruction always runs, so that we can carry on from a
+    /// breakpoint. Returns whether there's anything left to run.
+    fn run_with(&mut self, hooks: &mut [&mut dyn Hook]) -> Result<bool, RuntimeError> {
+        let mut first = true;
+        while !self.finished() {
+            if !first && hooks.iter_mut().any(|h| h.before(self) == Control::Break) {
+                return Ok(true);
+            }
+            first = false;
+
+            let pc = self.pc;
+            let effect = self.step()?;
+            // Every hook gets to see the instruction, even if an earlier one
+            // already asked us to stop.
+            let mut stop = false;
+            for h in hooks.iter_mut() {
+                stop |= h.after(self, pc, &effect) == Control::Break;
+            }
+            if stop {
+                return Ok(!self.finished());
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+fn main() {
+    let mut step_limit = DEFAULT_STEP_LIMIT;
+    let mut debug = None;
+    let mut show_extremes = false;
+    let mut check = false;
+    let mut timeline_path = None;
+    let mut rewind_to = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--step-limit" => {
+                step_limit = args.next().expect("step limit").parse().expect("step limit");
+            }
+            // The program comes from a file as we read the commands from stdin
+            "--debug" => debug = Some(args.next().expect("path to the program")),
+            "--extremes" => show_extremes = true,
+            "--repl" => {
+                repl::repl(step_limit);
+                return;
+            }
+            // Look for mistakes in the program before running it
+            "--check" => check = true,
+            "--timeline" => timeline_path = Some(args.next().expect("path for --timeline")),
+            // Show the registers as they were after this many steps
+            "--rewind" => {
+                rewind_to = Some(args.next().expect("step to rewind to").parse::<usize>().expect("step to rewind to"));
+            }
+            "--bench" => {
+                match args.next().and_then(|s| s.parse::<usize>().ok()) {
+                    Some(size) if size > 0 => bench::bench(size),
+                    _ => {
+                        eprintln!("error: --bench needs a program size above zero");
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+
+    let mut input = String::new();
+    match debug {
+        Some(ref path) => {
+            let mut f = std::fs::File::open(path).expect("open program");
+            f.read_to_string(&mut input).expect("read program");
+        }
+        None => {
+            std::io::stdin().read_to_string(&mut input).expect("read from stdin");
+        }
+    }
+    let insns = match parser::parse(&input) {
+        Ok(insns) => insns,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("error: {}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    if check {
+        for w in analysis::analyse(&insns) {
+            eprintln!("warning: {}", w);
+        }
+    }
+
+    if debug.is_some() {
+        debugger::debug(&insns, step_limit);
+        return;
+    }
+
+    let compiled = compile::compile(&insns);
+    let mut extremes = extremes::Extremes::new(compiled.names.len());
+    // Only keep the log if we're going to use it, as it grows with every step
+    let mut timeline = if timeline_path.is_some() || rewind_to.is_some() {
+        Some(timeline::Timeline::default())
+    } else {
+        None
+    };
+
+    let mut machine = Machine::with_registers(&compiled.program, compiled.registers(), step_limit);
+    while !machine.finished() {
+        let (pc, step) = (machine.pc, machine.steps);
+        match machine.step() {
+            Ok(effect) => {
+                if let Some((&slot, _, v)) = effect.write {
+                    extremes.record(slot, v, pc, step);
+                }
+                if let Some(ref mut t) = timeline {
+                    t.record(pc, &effect);
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    println!("Largest value {:?}", machine.regs.largest());
+    println!("Largest value at any point {:?}", extremes.peak(&compiled.names));
+
+    if show_extremes {
+        print_extremes(&compiled, &extremes);
+    }
+
+    if let Some(t) = timeline {
+        if let Some(path) = timeline_path {
+            let mut f = std::fs::File::create(&path).expect("create timeline file");
+            t.write_csv(&mut f, &compiled).expect("write timeline");
+        }
+        if let Some(step) = rewind_to {
+            let step = step.min(t.changes.len());
+            let mut values = machine.regs.values.clone();
+            t.seek(&mut values, t.changes.len(), step);
+            println!("Registers after {} steps:", step);
+            let mut slots = (0..compiled.names.len()).collect::<Vec<_>>();
+            slots.sort_by_key(|&slot| &compiled.names[slot]);
+            for slot in slots {
+                println!("  {} = {}", compiled.names[slot], values[slot]);
+            }
+        }
+    }
+}
+
+/// Show the lowest and highest value of each register, and where they were set
+fn print_extremes(compiled: &compile::Compiled, extremes: &extremes::Extremes) {
+    let mut slots = (0..compiled.names.len()).collect::<Vec<_>>();
+    slots.sort_by_key(|&slot| &compiled.names[slot]);
+    for slot in slots {
+        let h = &extremes.registers[slot];
+        match (h.min(), h.max()) {
+            (Some(min), Some(max)) => {
+                println!("{}: min {} at line {} ({} new lows), max {} at line {} ({} new highs)",
+                         compiled.names[slot],
+                         min.value, compiled.program[min.pc].line, h.lows.len(),
+                         max.value, compiled.program[max.pc].line, h.highs.len());
+            }
+            _ => println!("{}: never written", compiled.names[slot]),
+        }
+    }
+}
+
+/// Run the instruction at index `pc`
+fn execute<'a, F: RegisterFile>(i: &'a Instruction<F::Reg>, pc: usize, regs: &mut F)
+                                -> Result<Effect<'a, F::Reg>, RuntimeError> {
+    let mut effect = Effect {
+        next: pc as i64 + 1,
+        matched: None,
+        write: None,
+    };
+
+    if let Some(ref cond) = i.cond {
+        let matched = condition_matches(cond, regs);
+        effect.matched = Some(matched);
+        if !matched {
+            return Ok(effect);
+        }
+    }
+
+    let (register, op) = match i.action {
+        Action::Update { ref register, ref op } => (register, op),
+        Action::Jump(Target::Absolute(target)) => {
+            effect.next = target as i64;
+            return Ok(effect);
+        }
+        Action::Jump(Target::Relative(offset)) => {
+            // Only a jump forwards can overflow, and it's past the end anyway
+            effect.next = (pc as i64)
+                .checked_add(offset)
+                .ok_or(RuntimeError::JumpOutOfRange { line: i.line, target: i64::MAX })?;
+            return Ok(effect);
+        }
+    };
+
+    let v = regs.get(register);
+    let res = match *op {
+        Operation::Inc(ref d) => v.checked_add(d.value(regs)),
+        Operation::Dec(ref d) => v.checked_sub(d.value(regs)),
+        Operation::Mul(ref d) => v.checked_mul(d.value(regs)),
+        Operation::Div(ref d) | Operation::Mod(ref d) => {
+            let d = d.value(regs);
+            if d == 0 {
+                return Err(RuntimeError::DivisionByZero { line: i.line, register: regs.name(register) });
+            }
+            match *op {
+                Operation::Div(_) => v.checked_div(d),
+                _ => v.checked_rem(d),
+            }
+        }
+        Operation::Set(ref d) => Some(d.value(regs)),
+    };
+
+    match res {
+        Some(nv) => {
+            regs.set(register, nv);
+            effect.write = Some((register, v, nv));
+            Ok(effect)
+        }
+        None => Err(RuntimeError::Overflow { line: i.line, register: regs.name(register) }),
+    }
+}
+
+fn condition_matches<F: RegisterFile>(c: &Condition<F::Reg>, regs: &F) -> bool {
+    let (reg, op, value) = match *c {
+        Condition::Compare { ref reg, op, ref value } => (reg, op, value),
+        Condition::And(ref a, ref b) => return condition_matches(a, regs) && condition_matches(b, regs),
+        Condition::Or(ref a, ref b) => return condition_matches(a, regs) || condition_matches(b, regs),
+        Condition::Not(ref a) => return !condition_matches(a, regs),
+    };
+
+    let rv = regs.get(reg);
+    let cv = value.value(regs);
+
+    match op {
+        Operator::GreaterThan => rv > cv,
+        Operator::GreaterThanOrEqualTo => rv >= cv,
+        Operator::SmallerThan => rv < cv,
+        Operator::SmallerThanOrEqualTo => rv <= cv,
+        Operator::EqualTo => rv == cv,
+        Operator::NotEqualTo => rv != cv,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use aoc2017::rng::XorShift;
+    use super::{Machine, Registers, RuntimeError, DEFAULT_STEP_LIMIT};
+
+    fn run_with_limit(program: &str, step_limit: usize) -> Result<Registers, RuntimeError> {
+        let insns = super::parser::parse(program).unwrap();
+        let mut m = Machine::new(&insns, step_limit);
+        while !m.finished() {
+            m.step()?;
+        }
+        Ok(m.regs)
+    }
+
+    fn run(program: &str) -> Result<Registers, RuntimeError> {
+        run_with_limit(program, DEFAULT_STEP_LIMIT)
+    }
+
+    fn run_compiled(program: &str) -> Result<Registers, RuntimeError> {
+        let insns = super::parser::parse(program).unwrap();
+        let compiled = super::compile::compile(&insns);
+        let mut m = Machine::with_registers(&compiled.program, compiled.registers(), DEFAULT_STEP_LIMIT);
+        while !m.finished() {
+            m.step()?;
+        }
+        Ok(m.regs.iter().map(|(name, v)| (name.to_owned(), v)).collect())
+    }
+
+    #[test]
+    fn sample_input() {
+        let regs = run(include_str!("../../../input8-sample.txt")).unwrap();
+        assert_eq!(Some(&1), regs.get("a"));
+        assert_eq!(Some(&-10), regs.get("c"));
+        assert_eq!(None, regs.get("b"));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let regs = run("a set 7 if a == 0
+                        b set a if a > 0
+                        b mul 3 if a == 7
+                        c set b if b == 21
+                        c div 4 if c >= 21
+                        d set -7 if c == 5
+                        d mod 4 if not d == 0
+                        e inc b if b > a and a > 0
+                        f dec 1 if a < 0 or (not b < 0 and c != d)").unwrap();
+        assert_eq!(7, regs["a"]);
+        assert_eq!(21, regs["b"]);
+        assert_eq!(5, regs["c"]);
+        assert_eq!(-3, regs["d"]);
+        assert_eq!(21, regs["e"]);
+        assert_eq!(-1, regs["f"]);
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(Err(RuntimeError::Overflow { line: 2, register: "a".into() }),
+                   run("a set 9223372036854775807 if a == 0\na inc 1 if a > 0"));
+        assert_eq!(Err(RuntimeError::Overflow { line: 2, register: "a".into() }),
+                   run("a set -9223372036854775808 if a == 0\na div -1 if a < 0"));
+        assert_eq!(Err(RuntimeError::DivisionByZero { line: 1, register: "a".into() }),
+                   run("a mod b if a == 0"));
+    }
+
+    #[test]
+    fn jumps() {
+        // Multiply by adding in a loop
+        let regs = run("a set 6
+                        b set 7
+                        loop:
+                        jmp end if b == 0
+                        c inc a
+                        b dec 1
+                        jmp loop
+                        end:").unwrap();
+        assert_eq!(42, regs["c"]);
+
+        let regs = run("a inc 1\njmp 2 if a < 3\nb inc 1\njmp -3 if a < 3").unwrap();
+        assert_eq!(3, regs["a"]);
+        assert_eq!(1, regs["b"]);
+    }
+
+    #[test]
+    fn jump_errors() {
+        assert_eq!(Err(RuntimeError::JumpOutOfRange { line: 2, target: -1 }),
+                   run("a inc 1\njmp -2"));
+        assert_eq!(Err(RuntimeError::JumpOutOfRange { line: 1, target: 3 }),
+                   run("jmp 3\na inc 1"));
+        assert_eq!(Err(RuntimeError::JumpOutOfRange { line: 2, target: i64::MAX }),
+                   run("a inc 1\njmp 9223372036854775807"));
+        assert!(run("jmp 2\na inc 1").is_ok());
+        assert_eq!(Err(RuntimeError::StepLimit(100)), run_with_limit("start:\njmp start", 100));
+    }
+
+    #[test]
+    fn compiled() {
+        fn check(program: &str) {
+            assert_eq!(run(program).unwrap(), run_compiled(program).unwrap());
+        }
+
+        check(include_str!("../../../input8-sample.txt"));
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20 {
+            check(&super::bench::generate(&mut rng, 50, 5, 10));
+        }
+    }
+
+    #[test]
+    fn largest_ignores_registers_only_read() {
+        // b gets a slot when compiled but is never set, so it can't beat a
+        let insns = super::parser::parse("a dec 5 if b < 1").unwrap();
+        let compiled = super::compile::compile(&insns);
+        let mut m = Machine::with_registers(&compiled.program, compiled.registers(), DEFAULT_STEP_LIMIT);
+        while !m.finished() {
+            m.step().unwrap();
+        }
+        assert_eq!(Some(("a", -5)), m.regs.largest());
+    }
+}
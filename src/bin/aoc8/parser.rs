@@ -0,0 +1,569 @@
+//! A hand-written tokenizer and parser for the register language. Each line
+//! holds an instruction like `b inc 5 if a > 1`, and may have any amount of
+//! whitespace between the words and a `#` comment at the end.
+//!
+//! The amounts and the values compared against can be registers as well as
+//! numbers, and conditions can be combined with `and`, `or`, `not` and
+//! parentheses, with `not` binding tightest and `or` loosest.
+//!
+//! A line can also hold a label like `loop:`, and `jmp loop` or `jmp -2`
+//! carry on from the label or a number of instructions away. The condition is
+//! optional for every instruction.
+
+use std::collections::HashMap;
+use super::{Action, Condition, Instruction, Operand, Operation, Operator, Target};
+
+/// Words which can't be used as register names
+const KEYWORDS: &[&str] = &["if", "and", "or", "not", "jmp"];
+
+/// Where in the input something is. Lines and columns start at 1 and `end` is
+/// the column just past the last character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.start)
+    }
+}
+
+/// The part of the instruction before the `if` is wrong
+#[derive(Debug, PartialEq)]
+pub struct InvalidOperation {
+    pub span: Span,
+    pub message: String,
+}
+
+/// The part of the instruction from the `if` onwards is wrong
+#[derive(Debug, PartialEq)]
+pub struct InvalidCondition {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Operation(InvalidOperation),
+    Condition(InvalidCondition),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParseError::Operation(ref e) => write!(f, "{}: invalid operation: {}", e.span, e.message),
+            ParseError::Condition(ref e) => write!(f, "{}: invalid condition: {}", e.span, e.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// A register name or a keyword like `inc` or `if`
+    Word(String),
+    /// A number, which we keep as text until we know it fits
+    Number(String),
+    Comparison(Operator),
+    OpenParen,
+    CloseParen,
+    Colon,
+    /// A character which can't start any token
+    Invalid(char),
+    /// The end of the line, or the start of a comment
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+/// Split a line into tokens. There is always an `End` token at the end.
+fn tokenize(line_no: usize, line: &str) -> Vec<Token> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        let kind = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '#' {
+            break;
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            TokenKind::Word(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i+1).is_some_and(|c| c.is_ascii_digit())) {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            TokenKind::Number(chars[start..i].iter().collect())
+        } else {
+            let next = chars.get(i+1).cloned();
+            let (op, len) = match (c, next) {
+                ('>', Some('=')) => (Some(Operator::GreaterThanOrEqualTo), 2),
+                ('<', Some('=')) => (Some(Operator::SmallerThanOrEqualTo), 2),
+                ('=', Some('=')) => (Some(Operator::EqualTo), 2),
+                ('!', Some('=')) => (Some(Operator::NotEqualTo), 2),
+                ('>', _) => (Some(Operator::GreaterThan), 1),
+                ('<', _) => (Some(Operator::SmallerThan), 1),
+                _ => (None, 1),
+            };
+            i += len;
+            match (op, c) {
+                (Some(op), _) => TokenKind::Comparison(op),
+                (None, '(') => TokenKind::OpenParen,
+                (None, ')') => TokenKind::CloseParen,
+                (None, ':') => TokenKind::Colon,
+                (None, _) => TokenKind::Invalid(c),
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            span: Span { line: line_no, start: start + 1, end: i + 1 },
+        });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        span: Span { line: line_no, start: i + 1, end: i + 1 },
+    });
+
+    tokens
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match *kind {
+        TokenKind::Word(ref w) => format!("`{}`", w),
+        TokenKind::Number(ref n) => format!("`{}`", n),
+        TokenKind::Comparison(ref op) => format!("`{}`", op),
+        TokenKind::OpenParen => "`(`".into(),
+        TokenKind::CloseParen => "`)`".into(),
+        TokenKind::Colon => "`:`".into(),
+        TokenKind::Invalid(c) => format!("`{}`", c),
+        TokenKind::End => "the end of the line".into(),
+    }
+}
+
+/// Goes through the tokens of a single line
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &TokenKind {
+        &self.tokens[self.pos].kind
+    }
+
+    fn next(&mut self) -> Token {
+        let t = self.tokens[self.pos].clone();
+        if t.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        t
+    }
+
+    /// Skip over the keyword if it's next
+    fn eat(&mut self, keyword: &str) -> bool {
+        match *self.peek() {
+            TokenKind::Word(ref w) if w == keyword => {}
+            _ => return false,
+        }
+        self.pos += 1;
+        true
+    }
+
+    fn operation_error(t: &Token, expected: &str) -> ParseError {
+        ParseError::Operation(InvalidOperation {
+            span: t.span,
+            message: format!("expected {}, found {}", expected, describe(&t.kind)),
+        })
+    }
+
+    fn condition_error(t: &Token, expected: &str) -> ParseError {
+        ParseError::Condition(InvalidCondition {
+            span: t.span,
+            message: format!("expected {}, found {}", expected, describe(&t.kind)),
+        })
+    }
+
+    fn register(t: &Token) -> Option<String> {
+        match t.kind {
+            TokenKind::Word(ref w) if !KEYWORDS.contains(&w.as_str()) => Some(w.clone()),
+            _ => None,
+        }
+    }
+
+    fn operand(t: &Token) -> Option<Operand> {
+        match t.kind {
+            TokenKind::Number(ref n) => n.parse().ok().map(Operand::Value),
+            _ => Self::register(t).map(Operand::Register),
+        }
+    }
+
+    fn operation(&mut self) -> Result<(String, Operation), ParseError> {
+        let t = self.next();
+        let reg = Self::register(&t).ok_or_else(|| Self::operation_error(&t, "a register"))?;
+
+        let t = self.next();
+        let op = match t.kind {
+            TokenKind::Word(ref w) => match w.as_str() {
+                "inc" => Operation::Inc,
+                "dec" => Operation::Dec,
+                "mul" => Operation::Mul,
+                "div" => Operation::Div,
+                "mod" => Operation::Mod,
+                "set" => Operation::Set,
+                _ => return Err(Self::operation_error(&t, "an operation")),
+            },
+            _ => return Err(Self::operation_error(&t, "an operation")),
+        };
+
+        let t = self.next();
+        let amount = Self::operand(&t).ok_or_else(|| Self::operation_error(&t, "a number or register"))?;
+
+        Ok((reg, op(amount)))
+    }
+
+    /// The optional condition at the end of the line
+    fn condition(&mut self) -> Result<Option<Condition>, ParseError> {
+        let cond = if self.eat("if") {
+            Some(self.or()?)
+        } else {
+            None
+        };
+
+        let t = self.next();
+        if t.kind != TokenKind::End {
+            let expected = if cond.is_some() { "the end of the line" } else { "`if` or the end of the line" };
+            return Err(Self::condition_error(&t, expected));
+        }
+
+        Ok(cond)
+    }
+
+    fn or(&mut self) -> Result<Condition, ParseError> {
+        let mut c = self.and()?;
+        while self.eat("or") {
+            c = Condition::Or(Box::new(c), Box::new(self.and()?));
+        }
+        Ok(c)
+    }
+
+    fn and(&mut self) -> Result<Condition, ParseError> {
+        let mut c = self.not()?;
+        while self.eat("and") {
+            c = Condition::And(Box::new(c), Box::new(self.not()?));
+        }
+        Ok(c)
+    }
+
+    fn not(&mut self) -> Result<Condition, ParseError> {
+        if self.eat("not") {
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+
+        if *self.peek() == TokenKind::OpenParen {
+            self.next();
+            let c = self.or()?;
+            let t = self.next();
+            if t.kind != TokenKind::CloseParen {
+                return Err(Self::condition_error(&t, "`)`"));
+            }
+            return Ok(c);
+        }
+
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Condition, ParseError> {
+        let t = self.next();
+        let reg = Self::register(&t).ok_or_else(|| Self::condition_error(&t, "a register"))?;
+
+        let t = self.next();
+        let op = match t.kind {
+            TokenKind::Comparison(op) => op,
+            _ => return Err(Self::condition_error(&t, "a comparison")),
+        };
+
+        let t = self.next();
+        let value = Self::operand(&t).ok_or_else(|| Self::condition_error(&t, "a number or register"))?;
+
+        Ok(Condition::Compare { reg, op, value })
+    }
+}
+
+/// A line of the program, before we know where the labels are
+#[derive(Debug)]
+pub enum Line {
+    /// Nothing but whitespace or a comment
+    Empty,
+    Label(String, Span),
+    Instruction(Instruction),
+    /// A jump to a label, which we can only resolve once we've seen the whole
+    /// program
+    JumpToLabel {
+        line: usize,
+        label: String,
+        span: Span,
+        cond: Option<Condition>,
+    },
+}
+
+/// Parse a single line
+pub fn parse_line(line_no: usize, line: &str) -> Result<Line, ParseError> {
+    let mut p = Parser {
+        tokens: tokenize(line_no, line),
+        pos: 0,
+    };
+
+    match (&p.tokens[0].kind, p.tokens.get(1).map(|t| &t.kind)) {
+        (TokenKind::End, _) => return Ok(Line::Empty),
+        (TokenKind::Word(name), Some(TokenKind::Colon)) if !KEYWORDS.contains(&name.as_str()) => {
+            let label = Line::Label(name.clone(), p.tokens[0].span);
+            p.pos = 2;
+            let t = p.next();
+            if t.kind != TokenKind::End {
+                return Err(Parser::operation_error(&t, "the end of the line"));
+            }
+            return Ok(label);
+        }
+        _ => {}
+    }
+
+    if p.eat("jmp") {
+        let t = p.next();
+        let target = match t.kind {
+            TokenKind::Number(ref n) => match n.parse() {
+                Ok(offset) => Target::Relative(offset),
+                Err(_) => return Err(Parser::operation_error(&t, "an offset or label")),
+            },
+            _ => match Parser::register(&t) {
+                Some(label) => {
+                    let cond = p.condition()?;
+                    return Ok(Line::JumpToLabel { line: line_no, label, span: t.span, cond });
+                }
+                None => return Err(Parser::operation_error(&t, "an offset or label")),
+            },
+        };
+
+        let cond = p.condition()?;
+        return Ok(Line::Instruction(Instruction { line: line_no, action: Action::Jump(target), cond }));
+    }
+
+    let (register, op) = p.operation()?;
+    let cond = p.condition()?;
+
+    Ok(Line::Instruction(Instruction { line: line_no, action: Action::Update { register, op }, cond }))
+}
+
+/// Parse a condition on its own, without the `if`
+pub fn parse_condition(line_no: usize, text: &str) -> Result<Condition, ParseError> {
+    let mut p = Parser {
+        tokens: tokenize(line_no, text),
+        pos: 0,
+    };
+
+    let cond = p.or()?;
+    let t = p.next();
+    if t.kind != TokenKind::End {
+        return Err(Parser::condition_error(&t, "the end of the line"));
+    }
+
+    Ok(cond)
+}
+
+/// Parse a whole program, reporting every line which has a problem.
+pub fn parse(input: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    let mut insns = Vec::new();
+    let mut errors = Vec::new();
+    let mut labels = HashMap::new();
+    // The jumps to labels, with where they go in the program
+    let mut jumps = Vec::new();
+
+    for (i, line) in input.lines().enumerate() {
+        match parse_line(i + 1, line) {
+            Ok(Line::Empty) => {}
+            Ok(Line::Label(name, span)) => {
+                if labels.insert(name.clone(), insns.len()).is_some() {
+                    errors.push(ParseError::Operation(InvalidOperation {
+                        span,
+                        message: format!("label `{}` is already defined", name),
+                    }));
+                }
+            }
+            Ok(Line::Instruction(ins)) => insns.push(ins),
+            Ok(Line::JumpToLabel { line, label, span, cond }) => {
+                jumps.push((insns.len(), label, span));
+                // We fill in the target once we've seen every label
+                insns.push(Instruction { line, action: Action::Jump(Target::Absolute(0)), cond });
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    for (idx, label, span) in jumps {
+        match labels.get(&label) {
+            Some(&target) => insns[idx].action = Action::Jump(Target::Absolute(target)),
+            None => errors.push(ParseError::Operation(InvalidOperation {
+                span,
+                message: format!("there is no label `{}`", label),
+            })),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(insns)
+    } else {
+        errors.sort_by_key(|e| match *e {
+            ParseError::Operation(ref e) => e.span.line,
+            ParseError::Condition(ref e) => e.span.line,
+        });
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn span(line: usize, start: usize, end: usize) -> Span {
+        Span { line, start, end }
+    }
+
+    #[test]
+    fn whitespace_and_comments() {
+        let insns = parse("# a comment\n\n   b   inc 5 if a>1   # another\n\tc dec -10 if a >= -1\n").unwrap();
+        assert_eq!(2, insns.len());
+        assert_eq!(3, insns[0].line);
+        assert_eq!(update("b", Operation::Inc(Operand::Value(5))), insns[0].action);
+        assert_eq!(Some(compare("a", Operator::GreaterThan, Operand::Value(1))), insns[0].cond);
+        assert_eq!(update("c", Operation::Dec(Operand::Value(-10))), insns[1].action);
+        assert_eq!(Some(compare("a", Operator::GreaterThanOrEqualTo, Operand::Value(-1))), insns[1].cond);
+    }
+
+    fn update(register: &str, op: Operation) -> Action {
+        Action::Update { register: register.into(), op }
+    }
+
+    fn instruction(line: &str) -> Instruction {
+        match parse_line(1, line).unwrap() {
+            Line::Instruction(ins) => ins,
+            l => panic!("not an instruction: {:?}", l),
+        }
+    }
+
+    fn compare(reg: &str, op: Operator, value: Operand) -> Condition {
+        Condition::Compare { reg: reg.into(), op, value }
+    }
+
+    #[test]
+    fn registers_and_compound_conditions() {
+        let ins = instruction("a mul b if not c > d and (e == 1 or f != g) or h < 0");
+        assert_eq!(update("a", Operation::Mul(Operand::Register("b".into()))), ins.action);
+
+        let reg = |r: &str| Operand::Register(r.into());
+        let c_gt_d = Condition::Not(Box::new(compare("c", Operator::GreaterThan, reg("d"))));
+        let e_or_f = Condition::Or(Box::new(compare("e", Operator::EqualTo, Operand::Value(1))),
+                                   Box::new(compare("f", Operator::NotEqualTo, reg("g"))));
+        let expected = Condition::Or(Box::new(Condition::And(Box::new(c_gt_d), Box::new(e_or_f))),
+                                     Box::new(compare("h", Operator::SmallerThan, Operand::Value(0))));
+        assert_eq!(Some(expected), ins.cond);
+
+        let e = parse_line(1, "a set 1 if (b > 1 or c > 1").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 27, 27),
+            message: "expected `)`, found the end of the line".into(),
+        }), e);
+
+        let e = parse_line(1, "a set 1 if and > 1").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 12, 15),
+            message: "expected a register, found `and`".into(),
+        }), e);
+    }
+
+    #[test]
+    fn operation_errors() {
+        let e = parse_line(1, "b pow 5 if a > 1").unwrap_err();
+        assert_eq!(ParseError::Operation(InvalidOperation {
+            span: span(1, 3, 6),
+            message: "expected an operation, found `pow`".into(),
+        }), e);
+
+        let e = parse_line(2, "b inc if if a > 1").unwrap_err();
+        assert_eq!(ParseError::Operation(InvalidOperation {
+            span: span(2, 7, 9),
+            message: "expected a number or register, found `if`".into(),
+        }), e);
+
+        let e = parse_line(3, "b inc 99999999999999999999 if a > 1").unwrap_err();
+        assert_eq!(span(3, 7, 27), match e { ParseError::Operation(e) => e.span, _ => panic!() });
+    }
+
+    #[test]
+    fn condition_errors() {
+        let e = parse_line(1, "b inc 5 if a => 1").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 14, 15),
+            message: "expected a comparison, found `=`".into(),
+        }), e);
+
+        let e = parse_line(1, "b inc 5 a > 1").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 9, 10),
+            message: "expected `if` or the end of the line, found `a`".into(),
+        }), e);
+
+        let e = parse_line(1, "b inc 5 if a > 1 2").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 18, 19),
+            message: "expected the end of the line, found `2`".into(),
+        }), e);
+
+        let e = parse_line(1, "b inc 5 if a >").unwrap_err();
+        assert_eq!(ParseError::Condition(InvalidCondition {
+            span: span(1, 15, 15),
+            message: "expected a number or register, found the end of the line".into(),
+        }), e);
+    }
+
+    #[test]
+    fn labels_and_jumps() {
+        let insns = parse("start:\n  a inc 1\n  jmp start if a < 5 # loop\njmp -1\njmp end\nend:\n").unwrap();
+        assert_eq!(4, insns.len());
+        assert_eq!(None, insns[0].cond);
+        assert_eq!(Action::Jump(Target::Absolute(0)), insns[1].action);
+        assert_eq!(Some(compare("a", Operator::SmallerThan, Operand::Value(5))), insns[1].cond);
+        assert_eq!(Action::Jump(Target::Relative(-1)), insns[2].action);
+        assert_eq!(Action::Jump(Target::Absolute(4)), insns[3].action);
+
+        let errors = parse("a:\njmp b\na:\njmp if a > 1").unwrap_err();
+        assert_eq!(vec![
+            ParseError::Operation(InvalidOperation {
+                span: span(2, 5, 6),
+                message: "there is no label `b`".into(),
+            }),
+            ParseError::Operation(InvalidOperation {
+                span: span(3, 1, 2),
+                message: "label `a` is already defined".into(),
+            }),
+            ParseError::Operation(InvalidOperation {
+                span: span(4, 5, 7),
+                message: "expected an offset or label, found `if`".into(),
+            }),
+        ], errors);
+    }
+}
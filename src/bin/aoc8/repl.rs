@@ -0,0 +1,228 @@
+//! An interactive shell which runs each instruction as soon as it's typed,
+//! keeping the registers around between lines.
+
+use std::io::{BufRead, Write};
+use super::{execute, parser, Action, Machine, Registers};
+
+const HELP: &str = "\
+INSTRUCTION        run an instruction, e.g. `a inc 5 if b < 1`
+:regs              show every register
+:reset             set every register back to zero
+:load FILE         run the program in FILE
+:save FILE         write a program which sets the registers as they are now
+:history           show what has been typed so far
+!N                 run line N of the history again
+:quit              exit";
+
+/// What the shell remembers between lines
+pub struct Session {
+    pub regs: Registers,
+    /// Every line typed, with `!N` replaced by the line it repeats
+    pub history: Vec<String>,
+    step_limit: usize,
+}
+
+impl Session {
+    pub fn new(step_limit: usize) -> Self {
+        Session {
+            regs: Registers::new(),
+            history: Vec::new(),
+            step_limit,
+        }
+    }
+
+    /// Run a line of input, returning false when it's time to stop
+    pub fn handle<W: Write>(&mut self, line: &str, out: &mut W) -> std::io::Result<bool> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(true);
+        }
+
+        if let Some(n) = line.strip_prefix('!') {
+            return match n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|n| self.history.get(n)) {
+                Some(earlier) => {
+                    let earlier = earlier.clone();
+                    writeln!(out, "{}", earlier)?;
+                    self.handle(&earlier, out)
+                }
+                None => {
+                    writeln!(out, "error: no line {} in the history", n)?;
+                    Ok(true)
+                }
+            };
+        }
+        self.history.push(line.to_owned());
+
+        let (word, rest) = match line.find(' ') {
+            Some(pos) => (&line[..pos], line[pos..].trim()),
+            None => (line, ""),
+        };
+        match word {
+            ":quit" | ":q" => return Ok(false),
+            ":regs" => {
+                let mut names = self.regs.keys().collect::<Vec<&String>>();
+                names.sort();
+                for name in names {
+                    writeln!(out, "  {} = {}", name, self.regs[name])?;
+                }
+            }
+            ":reset" => self.regs.clear(),
+            ":load" if !rest.is_empty() => self.load(rest, out)?,
+            ":save" if !rest.is_empty() => {
+                if let Err(e) = self.save(rest) {
+                    writeln!(out, "error: writing {}: {}", rest, e)?;
+                }
+            }
+            ":history" => {
+                for (i, l) in self.history.iter().enumerate() {
+                    writeln!(out, "{:4}  {}", i + 1, l)?;
+                }
+            }
+            _ if word.starts_with(':') => writeln!(out, "{}", HELP)?,
+            _ => self.run(line, out)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Run a single instruction
+    fn run<W: Write>(&mut self, line: &str, out: &mut W) -> std::io::Result<()> {
+        let i = match parser::parse_line(self.history.len(), line) {
+            Ok(parser::Line::Instruction(i)) => i,
+            Ok(parser::Line::Empty) => return Ok(()),
+            Ok(_) => return writeln!(out, "error: labels and jumps only work in a program, try :load"),
+            Err(e) => return writeln!(out, "error: {}", e),
+        };
+        if let Action::Jump(_) = i.action {
+            return writeln!(out, "error: labels and jumps only work in a program, try :load");
+        }
+
+        match execute(&i, 0, &mut self.regs) {
+            Ok(effect) => match effect.write {
+                Some((register, old, new)) => writeln!(out, "  {} {} -> {}", register, old, new),
+                None => writeln!(out, "  condition failed"),
+            },
+            Err(e) => writeln!(out, "error: {}", e),
+        }
+    }
+
+    /// Run a program from a file on top of the registers we have. If it stops
+    /// with an error we keep whatever it had done until then.
+    fn load<W: Write>(&mut self, path: &str, out: &mut W) -> std::io::Result<()> {
+        let input = match std::fs::read_to_string(path) {
+            Ok(input) => input,
+            Err(e) => return writeln!(out, "error: reading {}: {}", path, e),
+        };
+        let program = match parser::parse(&input) {
+            Ok(program) => program,
+            Err(errors) => {
+                for e in errors {
+                    writeln!(out, "error: {}: {}", path, e)?;
+                }
+                return Ok(());
+            }
+        };
+
+        let regs = std::mem::take(&mut self.regs);
+        let mut m = Machine::with_registers(&program, regs, self.step_limit);
+        let mut result = Ok(());
+        while !m.finished() && result.is_ok() {
+            result = m.step().map(|_| ());
+        }
+        self.regs = m.regs;
+
+        match result {
+            Ok(()) => writeln!(out, "  ran {} instructions", m.steps),
+            Err(e) => writeln!(out, "error: {}", e),
+        }
+    }
+
+    /// Write the registers out as `set` instructions, so loading the file into
+    /// a fresh session gets back to where we are.
+    fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut names = self.regs.keys().collect::<Vec<&String>>();
+        names.sort();
+        let mut f = std::fs::File::create(path)?;
+        for name in names {
+            writeln!(f, "{} set {}", name, self.regs[name])?;
+        }
+        Ok(())
+    }
+}
+
+/// Read lines from stdin and run them until we run out or get told to stop
+pub fn repl(step_limit: usize) {
+    let mut session = Session::new(step_limit);
+    println!("Type instructions to run them, or :help for the commands");
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut line = String::new();
+    loop {
+        write!(stdout, "> ").expect("prompt");
+        stdout.flush().expect("flush");
+
+        line.clear();
+        if stdin.lock().read_line(&mut line).expect("stdin") == 0 {
+            break;
+        }
+        if !session.handle(&line, &mut stdout).expect("write to stdout") {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Session;
+    use super::super::DEFAULT_STEP_LIMIT;
+
+    fn type_lines(session: &mut Session, lines: &[&str]) -> String {
+        let mut out = Vec::new();
+        for l in lines {
+            session.handle(l, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let mut s = Session::new(DEFAULT_STEP_LIMIT);
+        assert_eq!("  a 0 -> 5\n  condition failed\n  b 0 -> 10\n",
+                   type_lines(&mut s, &["a inc 5", "b inc 1 if a > 5", "b set 10"]));
+        assert_eq!("  a = 5\n  b = 10\n", type_lines(&mut s, &[":regs"]));
+
+        // Going back through the history
+        assert_eq!("a inc 5\n  a 5 -> 10\n", type_lines(&mut s, &["!1"]));
+        assert_eq!("error: no line 9 in the history\n", type_lines(&mut s, &["!9"]));
+        assert_eq!(5, s.history.len());
+
+        let out = type_lines(&mut s, &["a inc", "jmp 1"]);
+        assert!(out.starts_with("error: 6:"));
+        assert!(out.ends_with("error: labels and jumps only work in a program, try :load\n"));
+        assert_eq!(10, s.regs["a"]);
+
+        type_lines(&mut s, &[":reset"]);
+        assert!(s.regs.is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("aoc8-repl-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut s = Session::new(DEFAULT_STEP_LIMIT);
+        type_lines(&mut s, &["a dec 7", "b inc 3 if a < 0", &format!(":save {}", path), ":reset"]);
+        assert_eq!("  ran 2 instructions\n", type_lines(&mut s, &[&format!(":load {}", path)]));
+        assert_eq!(-7, s.regs["a"]);
+        assert_eq!(3, s.regs["b"]);
+
+        // A program which fails keeps what it did before failing
+        std::fs::write(path, "c inc 1\nloop:\njmp loop").unwrap();
+        let mut s = Session::new(100);
+        assert_eq!("error: still running after 100 steps\n", type_lines(&mut s, &[&format!(":load {}", path)]));
+        assert_eq!(1, s.regs["c"]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
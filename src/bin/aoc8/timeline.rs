@@ -0,0 +1,134 @@
+//! A log of what every instruction did as the program ran, which is enough to
+//! get the registers back to how they were at any point and play them forward
+//! again.
+
+use std::io::Write;
+
+use super::compile::Compiled;
+use super::Effect;
+
+/// What running one instruction did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    /// The index of the instruction
+    pub pc: usize,
+    /// Whether the condition matched, if there was one
+    pub matched: Option<bool>,
+    /// The slot which was written to with its old and new values
+    pub write: Option<(usize, i64, i64)>,
+}
+
+/// Every change in the order the instructions ran
+#[derive(Debug, Default)]
+pub struct Timeline {
+    pub changes: Vec<Change>,
+}
+
+impl Timeline {
+    pub fn record(&mut self, pc: usize, effect: &Effect<usize>) {
+        self.changes.push(Change {
+            pc,
+            matched: effect.matched,
+            write: effect.write.map(|(&slot, old, new)| (slot, old, new)),
+        });
+    }
+
+    /// Take the register slots from how they were after `from` steps to how
+    /// they were after `to` steps, undoing the changes in between if we're
+    /// going back or redoing them if we're going forward.
+    pub fn seek(&self, values: &mut [i64], from: usize, to: usize) {
+        assert!(from <= self.changes.len() && to <= self.changes.len(), "step out of range");
+        if to < from {
+            for c in self.changes[to..from].iter().rev() {
+                if let Some((slot, old, _)) = c.write {
+                    values[slot] = old;
+                }
+            }
+        } else {
+            for c in &self.changes[from..to] {
+                if let Some((slot, _, new)) = c.write {
+                    values[slot] = new;
+                }
+            }
+        }
+    }
+
+    /// Write the timeline out as CSV, with one row for each instruction which
+    /// ran. The register columns are empty if nothing was written.
+    pub fn write_csv<W: Write>(&self, w: &mut W, compiled: &Compiled) -> std::io::Result<()> {
+        writeln!(w, "step,pc,line,matched,register,old,new")?;
+        for (step, c) in self.changes.iter().enumerate() {
+            write!(w, "{},{},{},", step, c.pc, compiled.program[c.pc].line)?;
+            if let Some(matched) = c.matched {
+                write!(w, "{}", matched)?;
+            }
+            match c.write {
+                Some((slot, old, new)) => writeln!(w, ",{},{},{}", compiled.names[slot], old, new)?,
+                None => writeln!(w, ",,,")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Timeline;
+    use super::super::{compile, parser, Machine, DEFAULT_STEP_LIMIT};
+
+    const PROGRAM: &str = "a inc 2
+                           loop:
+                           b inc a if a > 1
+                           a dec 1
+                           jmp loop if a > 0";
+
+    fn run(compiled: &compile::Compiled) -> (Timeline, Vec<i64>) {
+        let mut timeline = Timeline::default();
+        let mut m = Machine::with_registers(&compiled.program, compiled.registers(), DEFAULT_STEP_LIMIT);
+        while !m.finished() {
+            let pc = m.pc;
+            let effect = m.step().unwrap();
+            timeline.record(pc, &effect);
+        }
+        (timeline, m.regs.values)
+    }
+
+    #[test]
+    fn rewind_and_replay() {
+        let compiled = compile::compile(&parser::parse(PROGRAM).unwrap());
+        let (timeline, end) = run(&compiled);
+        assert_eq!(7, timeline.changes.len());
+        assert_eq!(vec![0, 2], end);
+
+        // Before anything ran everything is zero
+        let mut values = end.clone();
+        timeline.seek(&mut values, 7, 0);
+        assert_eq!(vec![0, 0], values);
+
+        // After the first time round the loop
+        timeline.seek(&mut values, 0, 4);
+        assert_eq!(vec![1, 2], values);
+        timeline.seek(&mut values, 4, 2);
+        assert_eq!(vec![2, 2], values);
+
+        timeline.seek(&mut values, 2, 7);
+        assert_eq!(end, values);
+    }
+
+    #[test]
+    fn csv() {
+        let compiled = compile::compile(&parser::parse(PROGRAM).unwrap());
+        let (timeline, _) = run(&compiled);
+        let mut out = Vec::new();
+        timeline.write_csv(&mut out, &compiled).unwrap();
+        assert_eq!("step,pc,line,matched,register,old,new\n\
+                    0,0,1,,a,0,2\n\
+                    1,1,3,true,b,0,2\n\
+                    2,2,4,,a,2,1\n\
+                    3,3,5,true,,,\n\
+                    4,1,3,false,,,\n\
+                    5,2,4,,a,1,0\n\
+                    6,3,5,false,,,\n",
+                   String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,293 @@
+//! Parse a stream into a tree of groups and garbage, so we can ask questions
+//! about its structure without writing a new state machine for each one.
+
+/// A range of bytes in the input, with `end` just past the last one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// A group within braces, which holds the nodes separated by commas
+    Group { span: Span, children: Vec<Node> },
+    /// The characters between `<` and `>`, both as they were written and with
+    /// the `!` and the characters they cancel taken out
+    Garbage { span: Span, raw: String, cleaned: String },
+}
+
+/// Why the input isn't a valid stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The byte where we found the problem
+    pub offset: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "byte {}: {}", self.offset, self.message)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let found = match self.peek() {
+            Some(c) => format!("found {:?}", c),
+            None => "found the end of the stream".to_owned(),
+        };
+        ParseError {
+            offset: self.pos,
+            message: format!("expected {}, {}", message, found),
+        }
+    }
+
+    /// Whitespace between nodes doesn't mean anything, so streams can be
+    /// spread over several lines
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn node(&mut self) -> Result<Node, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.group(),
+            Some('<') => self.garbage(),
+            _ => Err(self.error("`{` or `<`")),
+        }
+    }
+
+    fn group(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut children = Vec::new();
+        self.skip_whitespace();
+        if self.peek() != Some('}') {
+            loop {
+                children.push(self.node()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(',') => self.pos += 1,
+                    Some('}') => break,
+                    _ => return Err(self.error("`,` or `}`")),
+                }
+            }
+        }
+        self.pos += 1;
+
+        Ok(Node::Group {
+            span: Span { start, end: self.pos },
+            children,
+        })
+    }
+
+    fn garbage(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        let mut cleaned = String::new();
+        let mut chars = self.input[start + 1..].char_indices();
+        loop {
+            match chars.next() {
+                Some((i, '>')) => {
+                    self.pos = start + 1 + i + 1;
+                    break;
+                }
+                Some((_, '!')) => {
+                    chars.next();
+                }
+                Some((_, c)) => cleaned.push(c),
+                None => {
+                    self.pos = self.input.len();
+                    return Err(self.error("`>`"));
+                }
+            }
+        }
+
+        Ok(Node::Garbage {
+            span: Span { start, end: self.pos },
+            raw: self.input[start + 1..self.pos - 1].to_owned(),
+            cleaned,
+        })
+    }
+}
+
+/// Parse a stream holding a single group or piece of garbage. Whitespace
+/// outside of garbage, like the newline at the end of a file, is fine.
+pub fn parse(input: &str) -> Result<Node, ParseError> {
+    let mut p = Parser { input, pos: 0 };
+    let node = p.node()?;
+    p.skip_whitespace();
+    if p.pos < p.input.len() {
+        return Err(p.error("the end of the stream"));
+    }
+    Ok(node)
+}
+
+/// Parse a stream which hasn't been checked to be UTF-8 yet. The scorers take
+/// any bytes in garbage, but the tree keeps it as text.
+pub fn parse_bytes(input: &[u8]) -> Result<Node, ParseError> {
+    match std::str::from_utf8(input) {
+        Ok(s) => parse(s),
+        Err(e) => Err(ParseError {
+            offset: e.valid_up_to(),
+            message: format!("expected UTF-8, found byte {:#04x}", input[e.valid_up_to()]),
+        }),
+    }
+}
+
+impl Node {
+    pub fn span(&self) -> Span {
+        match *self {
+            Node::Group { span, .. } | Node::Garbage { span, .. } => span,
+        }
+    }
+
+    pub fn children(&self) -> &[Node] {
+        match *self {
+            Node::Group { ref children, .. } => children,
+            Node::Garbage { .. } => &[],
+        }
+    }
+
+    /// Every node in the tree with its depth, parents before their children.
+    /// This node is at depth 1, or 0 if it's garbage.
+    pub fn walk(&self) -> Vec<(usize, &Node)> {
+        let mut out = Vec::new();
+        let mut stack = vec![(1, self)];
+        while let Some((depth, n)) = stack.pop() {
+            match *n {
+                Node::Group { ref children, .. } => {
+                    out.push((depth, n));
+                    stack.extend(children.iter().rev().map(|c| (depth + 1, c)));
+                }
+                Node::Garbage { .. } => out.push((depth - 1, n)),
+            }
+        }
+        out
+    }
+
+    /// How deep the groups go
+    pub fn depth(&self) -> usize {
+        self.walk().iter().map(|&(d, _)| d).max().unwrap_or(0)
+    }
+
+    /// The sum of the depths of every group, which is the answer to part 1
+    pub fn score(&self) -> u64 {
+        self.walk()
+            .iter()
+            .filter(|&&(_, n)| n.is_group())
+            .map(|&(d, _)| d as u64)
+            .sum()
+    }
+
+    pub fn groups(&self) -> usize {
+        self.walk().iter().filter(|&&(_, n)| n.is_group()).count()
+    }
+
+    /// The cleaned up contents of every piece of garbage in order
+    pub fn garbage(&self) -> Vec<&str> {
+        self.walk()
+            .into_iter()
+            .filter_map(|(_, n)| match *n {
+                Node::Garbage { ref cleaned, .. } => Some(cleaned.as_str()),
+                Node::Group { .. } => None,
+            })
+            .collect()
+    }
+
+    /// How many characters of garbage weren't cancelled, which is the answer
+    /// to part 2. Like `count_score` this counts bytes.
+    pub fn noncancelled(&self) -> u64 {
+        self.garbage().iter().map(|g| g.len() as u64).sum()
+    }
+
+    /// The nodes containing the byte at `offset`, from the outside in
+    pub fn path_to(&self, offset: usize) -> Vec<&Node> {
+        let mut path = Vec::new();
+        let mut node = self;
+        loop {
+            let span = node.span();
+            if offset < span.start || offset >= span.end {
+                break;
+            }
+            path.push(node);
+            match node.children().iter().find(|c| c.span().start <= offset && offset < c.span().end) {
+                Some(c) => node = c,
+                None => break,
+            }
+        }
+        path
+    }
+
+    fn is_group(&self) -> bool {
+        match *self {
+            Node::Group { .. } => true,
+            Node::Garbage { .. } => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_bytes, Node, Span};
+
+    #[test]
+    fn tree() {
+        let tree = parse("{{<a!>b>},{}}\n").unwrap();
+        assert_eq!(Node::Group {
+                       span: Span { start: 0, end: 13 },
+                       children: vec![
+                           Node::Group {
+                               span: Span { start: 1, end: 9 },
+                               children: vec![Node::Garbage {
+                                   span: Span { start: 2, end: 8 },
+                                   raw: "a!>b".into(),
+                                   cleaned: "ab".into(),
+                               }],
+                           },
+                           Node::Group { span: Span { start: 10, end: 12 }, children: vec![] },
+                       ],
+                   },
+                   tree);
+        assert_eq!(2, tree.depth());
+        assert_eq!(3, tree.groups());
+        assert_eq!(vec!["ab"], tree.garbage());
+        assert_eq!(vec![Span { start: 0, end: 13 }, Span { start: 1, end: 9 }, Span { start: 2, end: 8 }],
+                   tree.path_to(5).iter().map(|n| n.span()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn matches_count_score() {
+        for s in &["{}", "{{{}}}", "{{{},{},{{}}}}", "{<a>,<a>,<a>,<a>}", "{{<ab>},{<ab>},{<ab>},{<ab>}}",
+                   "{{<!!>},{<!!>},{<!!>},{<!!>}}", "{{<a!>},{<a!>},{<a!>},{<ab>}}",
+                   "<>", "<random characters>", "<<<<>", "<{!>}>", "<!!>", "<!!!>>", r#"<{o"i!a,<{i<a>"#] {
+            let tree = parse(s).unwrap();
+            assert_eq!(super::super::count_score(s.as_bytes()), (tree.score(), tree.noncancelled()), "{}", s);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("byte 3: expected `,` or `}`, found 'x'",
+                   parse("{{}x}").unwrap_err().to_string());
+        assert_eq!("byte 5: expected `>`, found the end of the stream",
+                   parse("{<ab!").unwrap_err().to_string());
+        assert_eq!("byte 2: expected the end of the stream, found '}'",
+                   parse("{}}").unwrap_err().to_string());
+        assert_eq!("byte 3: expected UTF-8, found byte 0xff",
+                   parse_bytes(b"{<a\xffb>}").unwrap_err().to_string());
+        // The tree counts bytes like the scorers do
+        assert_eq!(2, parse_bytes("{<é>}".as_bytes()).unwrap().noncancelled());
+    }
+}
@@ -0,0 +1,150 @@
+//! Write a parsed stream back out, either as it was or spread out with each
+//! group on its own lines so it's possible to read.
+
+use super::ast::Node;
+
+/// What to do with garbage when writing a stream out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Garbage {
+    /// Write it as it was, with the `!` and what they cancel
+    Raw,
+    /// Leave out the `!` and the characters they cancel
+    Cleaned,
+    /// Leave it out altogether
+    Strip,
+}
+
+/// The text of a piece of garbage, or nothing if we're leaving it out
+fn garbage(raw: &str, cleaned: &str, style: Garbage) -> Option<String> {
+    match style {
+        Garbage::Raw => Some(format!("<{}>", raw)),
+        Garbage::Cleaned => Some(format!("<{}>", cleaned)),
+        Garbage::Strip => None,
+    }
+}
+
+/// The children we're going to write out
+fn children(children: &[Node], style: Garbage) -> Vec<&Node> {
+    children
+        .iter()
+        .filter(|c| style != Garbage::Strip || matches!(**c, Node::Group { .. }))
+        .collect()
+}
+
+/// Write the stream on a single line. With raw garbage this gives back exactly
+/// what was parsed.
+pub fn compact(node: &Node, style: Garbage) -> String {
+    let mut out = String::new();
+    write_compact(node, style, &mut out);
+    out
+}
+
+fn write_compact(node: &Node, style: Garbage, out: &mut String) {
+    match *node {
+        Node::Group { children: ref c, .. } => {
+            out.push('{');
+            for (i, child) in children(c, style).into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_compact(child, style, out);
+            }
+            out.push('}');
+        }
+        Node::Garbage { ref raw, ref cleaned, .. } => {
+            if let Some(g) = garbage(raw, cleaned, style) {
+                out.push_str(&g);
+            }
+        }
+    }
+}
+
+/// Write the stream with every group and piece of garbage on its own line,
+/// indented by `indent` for each group it's in. Empty groups stay on one line.
+pub fn pretty(node: &Node, indent: &str, style: Garbage) -> String {
+    let mut out = String::new();
+    write_pretty(node, indent, style, 0, &mut out);
+    out
+}
+
+fn write_pretty(node: &Node, indent: &str, style: Garbage, depth: usize, out: &mut String) {
+    match *node {
+        Node::Group { children: ref c, .. } => {
+            let c = children(c, style);
+            if c.is_empty() {
+                out.push_str(&format!("{}{{}}", indent.repeat(depth)));
+                return;
+            }
+            out.push_str(&format!("{}{{\n", indent.repeat(depth)));
+            for (i, child) in c.iter().enumerate() {
+                write_pretty(child, indent, style, depth + 1, out);
+                if i + 1 < c.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            out.push_str(&format!("{}}}", indent.repeat(depth)));
+        }
+        Node::Garbage { ref raw, ref cleaned, .. } => {
+            if let Some(g) = garbage(raw, cleaned, style) {
+                out.push_str(&indent.repeat(depth));
+                out.push_str(&g);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compact, pretty, Garbage};
+    use super::super::ast::parse;
+
+    const STREAM: &str = "{{<a!>b>,{}},<x!!>,{{<>}}}";
+
+    #[test]
+    fn round_trip() {
+        for s in &[STREAM, include_str!("../../../input9.txt").trim_end()] {
+            let tree = parse(s).unwrap();
+            assert_eq!(*s, compact(&tree, Garbage::Raw));
+
+            // Spreading it out doesn't change anything either
+            let spread = parse(&pretty(&tree, "  ", Garbage::Raw)).unwrap();
+            assert_eq!(*s, compact(&spread, Garbage::Raw));
+        }
+    }
+
+    #[test]
+    fn garbage() {
+        let tree = parse(STREAM).unwrap();
+        assert_eq!("{{<ab>,{}},<x>,{{<>}}}", compact(&tree, Garbage::Cleaned));
+        assert_eq!("{{{}},{{}}}", compact(&tree, Garbage::Strip));
+
+        // Neither changes the score or what's in the garbage
+        for style in &[Garbage::Cleaned, Garbage::Strip] {
+            let other = parse(&compact(&tree, *style)).unwrap();
+            assert_eq!(tree.score(), other.score());
+            if *style == Garbage::Cleaned {
+                assert_eq!(tree.garbage(), other.garbage());
+            }
+        }
+    }
+
+    #[test]
+    fn indented() {
+        let tree = parse(STREAM).unwrap();
+        assert_eq!("{\n\
+                    \x20 {\n\
+                    \x20   <a!>b>,\n\
+                    \x20   {}\n\
+                    \x20 },\n\
+                    \x20 <x!!>,\n\
+                    \x20 {\n\
+                    \x20   {\n\
+                    \x20     <>\n\
+                    \x20   }\n\
+                    \x20 }\n\
+                    }",
+                   pretty(&tree, "  ", Garbage::Raw));
+        assert_eq!("{\n\t{\n\t\t{}\n\t},\n\t{\n\t\t{}\n\t}\n}", pretty(&tree, "\t", Garbage::Strip));
+    }
+}
@@ -0,0 +1,96 @@
+//! Random valid streams for which we know the answers, to check the different
+//! ways of scoring against.
+
+use aoc2017::rng::XorShift;
+
+/// A stream along with what scoring it should give
+#[derive(Debug)]
+pub struct Generated {
+    pub stream: String,
+    pub score: u64,
+    pub noncancelled: u64,
+    pub groups: usize,
+}
+
+/// What can show up in garbage, including the characters which mean
+/// something outside of it
+const GARBAGE: &[u8] = b"{}<,!ae'\"";
+
+/// Generate a group holding up to `size` groups and pieces of garbage, going
+/// no deeper than `max_depth`.
+pub fn generate(rng: &mut XorShift, size: usize, max_depth: usize) -> Generated {
+    let mut g = Generated {
+        stream: String::new(),
+        score: 0,
+        noncancelled: 0,
+        groups: 0,
+    };
+    let mut budget = size;
+    group(rng, &mut g, &mut budget, 1, max_depth);
+    g
+}
+
+fn group(rng: &mut XorShift, g: &mut Generated, budget: &mut usize, depth: usize, max_depth: usize) {
+    g.stream.push('{');
+    g.score += depth as u64;
+    g.groups += 1;
+
+    let children = if *budget == 0 { 0 } else { rng.next(5) };
+    for i in 0..children {
+        if i > 0 {
+            g.stream.push(',');
+        }
+        *budget = budget.saturating_sub(1);
+        if depth < max_depth && rng.next(3) > 0 {
+            group(rng, g, budget, depth + 1, max_depth);
+        } else {
+            garbage(rng, g);
+        }
+    }
+
+    g.stream.push('}');
+}
+
+fn garbage(rng: &mut XorShift, g: &mut Generated) {
+    g.stream.push('<');
+    for _ in 0..rng.next(8) {
+        let c = GARBAGE[rng.next(GARBAGE.len() as u64) as usize] as char;
+        g.stream.push(c);
+        if c == '!' {
+            // Anything can be cancelled, even the end of the garbage
+            g.stream.push(if rng.next(2) == 0 { '>' } else { '!' });
+        } else {
+            g.noncancelled += 1;
+        }
+    }
+    g.stream.push('>');
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use aoc2017::rng::XorShift;
+    use super::generate;
+    use super::super::{ast, count_score, parallel, scan};
+
+    #[test]
+    fn scorers_agree() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for i in 0..500 {
+            let g = generate(&mut rng, i, 1 + i % 12);
+            let bytes = g.stream.as_bytes();
+            let expected = (g.score, g.noncancelled);
+
+            assert_eq!(expected, count_score(Cursor::new(bytes)), "{}", g.stream);
+            assert!(scan(Cursor::new(bytes)).errors.is_empty(), "{}", g.stream);
+            assert_eq!(expected, {
+                let s = parallel::scan(bytes, 1 + i % 7);
+                (s.score, s.noncancelled)
+            });
+
+            let tree = ast::parse(&g.stream).unwrap();
+            assert_eq!(expected, (tree.score(), tree.noncancelled()));
+            assert_eq!(g.groups, tree.groups());
+        }
+    }
+}
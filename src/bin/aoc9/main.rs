@@ -0,0 +1,239 @@
+// Only the tests need the library, which keeps the fuzz target free of it
+#[cfg(test)]
+extern crate aoc2017;
+
+use std::io::{BufRead, Read};
+
+pub mod ast;
+mod format;
+#[cfg(test)]
+mod generate;
+pub mod parallel;
+pub mod stream;
+
+fn main() {
+    let mut tree = false;
+    let mut at = None;
+    let mut lenient = false;
+    let mut threads = None;
+    let mut format = None;
+    let mut compact = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tree" => tree = true,
+            // Show which nodes of the tree a byte is in
+            "--at" => {
+                tree = true;
+                at = Some(args.next().expect("byte offset").parse().expect("byte offset"));
+            }
+            // Count what we can of a malformed stream instead of stopping
+            "--lenient" => lenient = true,
+            // Split the stream up between this many threads
+            "--threads" => threads = Some(args.next().expect("thread count").parse().expect("thread count")),
+            // Write the stream back out with the garbage as it was (raw),
+            // without what's been cancelled (clean) or left out (strip)
+            "--format" => {
+                format = Some(match args.next().expect("garbage style").as_str() {
+                    "raw" => format::Garbage::Raw,
+                    "clean" => format::Garbage::Cleaned,
+                    "strip" => format::Garbage::Strip,
+                    s => panic!("unknown garbage style {}", s),
+                });
+            }
+            // Format on a single line instead of spreading it out
+            "--compact" => compact = true,
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+
+    let stdin = std::io::stdin();
+    if let Some(style) = format {
+        let tree = read_tree(stdin.lock());
+        if compact {
+            println!("{}", format::compact(&tree, style));
+        } else {
+            println!("{}", format::pretty(&tree, "  ", style));
+        }
+        return;
+    }
+    if tree {
+        print_tree(stdin.lock(), at);
+        return;
+    }
+
+    let scan = match threads {
+        Some(n) => {
+            let mut input = Vec::new();
+            stdin.lock().read_to_end(&mut input).expect("read from stdin");
+            parallel::scan(&input, n)
+        }
+        None => scan(stdin.lock()),
+    };
+    for e in &scan.errors {
+        eprintln!("{}: {}", if lenient { "warning" } else { "error" }, e);
+    }
+    if !lenient && !scan.errors.is_empty() {
+        std::process::exit(1);
+    }
+
+    println!("Score: {}, noncancelled: {}", scan.score, scan.noncancelled);
+}
+
+/// Parse the whole stream into a tree, stopping if it's not valid
+fn read_tree<R: Read>(mut r: R) -> ast::Node {
+    let mut input = Vec::new();
+    r.read_to_end(&mut input).expect("read from stdin");
+    match ast::parse_bytes(&input) {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parse the stream into a tree and describe it
+fn print_tree<R: Read>(r: R, at: Option<usize>) {
+    let tree = read_tree(r);
+
+    let garbage = tree.garbage();
+    println!("Score: {}, noncancelled: {}", tree.score(), tree.noncancelled());
+    println!("Groups: {}, deepest: {}", tree.groups(), tree.depth());
+    println!("Garbage: {} pieces, {} empty", garbage.len(), garbage.iter().filter(|g| g.is_empty()).count());
+
+    if let Some(offset) = at {
+        println!("Byte {} is in:", offset);
+        for n in tree.path_to(offset) {
+            let span = n.span();
+            match *n {
+                ast::Node::Group { ref children, .. } => {
+                    println!("  group {}..{} with {} children", span.start, span.end, children.len());
+                }
+                ast::Node::Garbage { ref raw, .. } => println!("  garbage {}..{}: {}", span.start, span.end, raw),
+            }
+        }
+    }
+}
+
+/// Something wrong with a stream, with the offset of the byte where it is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamError {
+    /// A `}` with no group to close
+    UnmatchedClose(usize),
+    /// A `{` which is never closed
+    UnclosedGroup(usize),
+    /// A `<` which is never closed
+    UnterminatedGarbage(usize),
+    /// A `!` at the very end, with nothing left to cancel
+    TrailingCancel(usize),
+}
+
+impl StreamError {
+    fn offset(&self) -> usize {
+        match *self {
+            StreamError::UnmatchedClose(o) | StreamError::UnclosedGroup(o) |
+            StreamError::UnterminatedGarbage(o) | StreamError::TrailingCancel(o) => o,
+        }
+    }
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StreamError::UnmatchedClose(offset) => write!(f, "byte {}: `}}` without a group to close", offset),
+            StreamError::UnclosedGroup(offset) => write!(f, "byte {}: group is never closed", offset),
+            StreamError::UnterminatedGarbage(offset) => write!(f, "byte {}: garbage is never closed", offset),
+            StreamError::TrailingCancel(offset) => write!(f, "byte {}: `!` at the end of the stream", offset),
+        }
+    }
+}
+
+/// The result of going through a stream
+#[derive(Debug, PartialEq)]
+pub struct Scan {
+    pub score: u64,
+    pub noncancelled: u64,
+    /// What was wrong with the stream, in the order of the offsets. The counts
+    /// skip any `}` which didn't match and the groups which never closed.
+    pub errors: Vec<StreamError>,
+}
+
+/// Go through everything the reader has, a buffer at a time
+pub fn scan<R: BufRead>(mut r: R) -> Scan {
+    let mut p = stream::StreamParser::new();
+    loop {
+        let n = {
+            let buf = r.fill_buf().expect("read stream");
+            p.feed(buf);
+            buf.len()
+        };
+        if n == 0 {
+            break;
+        }
+        r.consume(n);
+    }
+    p.finish()
+}
+
+/// The score and garbage count on their own, as the puzzle asks for them
+#[cfg(test)]
+fn count_score<R: BufRead>(r: R) -> (u64, u64) {
+    let scan = scan(r);
+    (scan.score, scan.noncancelled)
+}
+
+#[cfg(test)]
+macro_rules! count_string {
+    ($x:expr, $y:expr) => {
+        assert_eq!($x, count_score(Cursor::new($y.as_bytes())).0)
+    }
+}
+
+#[cfg(test)]
+macro_rules! count_noncancelled_string {
+    ($x:expr, $y:expr) => {
+        assert_eq!($x, count_score(Cursor::new($y.as_bytes())).1)
+    }
+}
+
+#[test]
+fn test_count_score() {
+    use std::io::Cursor;
+    count_string!(1, "{}");
+    count_string!(6, "{{{}}}");
+    count_string!(16, "{{{},{},{{}}}}");
+    count_string!(1, "{<a>,<a>,<a>,<a>}");
+    count_string!(9, "{{<ab>},{<ab>},{<ab>},{<ab>}}");
+    count_string!(9, "{{<!!>},{<!!>},{<!!>},{<!!>}}");
+    count_string!(3, "{{<a!>},{<a!>},{<a!>},{<ab>}}");
+}
+
+#[test]
+fn test_noncancelled() {
+    use std::io::Cursor;
+    count_noncancelled_string!(0, "<>");
+    count_noncancelled_string!(17, "<random characters>");
+    count_noncancelled_string!(3, "<<<<>");
+    count_noncancelled_string!(2, "<{!>}>");
+    count_noncancelled_string!(0, "<!!>");
+    count_noncancelled_string!(0, "<!!!!>>");
+    count_noncancelled_string!(10, r#"<{o"i!a,<{i<a>"#);
+}
+
+#[test]
+fn test_malformed() {
+    use std::io::Cursor;
+    use StreamError::*;
+
+    let s = scan(Cursor::new("{}}{<a>}".as_bytes()));
+    assert_eq!((2, 1), (s.score, s.noncancelled));
+    assert_eq!(vec![UnmatchedClose(2)], s.errors);
+
+    let s = scan(Cursor::new("{{},{<ab!".as_bytes()));
+    assert_eq!((2, 2), (s.score, s.noncancelled));
+    assert_eq!(vec![UnclosedGroup(0), UnclosedGroup(4), UnterminatedGarbage(5), TrailingCancel(8)], s.errors);
+
+    assert_eq!("byte 2: `}` without a group to close", UnmatchedClose(2).to_string());
+    assert_eq!(Vec::<StreamError>::new(), scan(Cursor::new("{{<!!>},{}}\n".as_bytes())).errors);
+}
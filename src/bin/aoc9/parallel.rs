@@ -0,0 +1,229 @@
+//! Score a stream on several threads. Each thread goes through its own chunk
+//! and sums it up without knowing how deep in groups the chunk starts, and then
+//! we put the summaries together in order.
+//!
+//! What a summary can't do without is whether the chunk starts inside garbage,
+//! so each thread sums its chunk up both ways. A chunk which starts right after
+//! a `!` is rare enough that we go through it again when putting them together.
+
+use super::{Scan, StreamError};
+
+/// The state carried from one chunk to the next, apart from the groups
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    in_garbage: bool,
+    /// The previous chunk ended in a `!` so we skip the first byte
+    skip: bool,
+}
+
+/// What a chunk does to the state, whatever the depth it starts at
+#[derive(Debug)]
+struct Summary {
+    /// The state the chunk was gone through from
+    entry: Entry,
+    /// The state at the end of the chunk
+    exit: Entry,
+    /// Where the garbage we're in at the end started, if it was in this chunk
+    garbage_start: Option<usize>,
+    noncancelled: u64,
+    /// Where each `}` which closed a group from before the chunk is
+    closes: Vec<usize>,
+    /// Where each `{` which is still open at the end of the chunk is
+    opens: Vec<usize>,
+    /// The score of the groups closed in the chunk if it started at depth zero
+    local_score: u64,
+    /// How many groups closed within the chunk after each number of `closes`.
+    /// Each of them scores that much more for every group still open from
+    /// before the chunk.
+    closed_after: Vec<u64>,
+}
+
+/// Go through a chunk which starts at `offset` in the stream
+fn summarise(chunk: &[u8], offset: usize, entry: Entry) -> Summary {
+    let mut s = Summary {
+        entry,
+        exit: entry,
+        garbage_start: None,
+        noncancelled: 0,
+        closes: Vec::new(),
+        opens: Vec::new(),
+        local_score: 0,
+        closed_after: vec![0],
+    };
+    let mut in_garbage = entry.in_garbage;
+    let mut skip_next = entry.skip;
+
+    for (i, &b) in chunk.iter().enumerate() {
+        if skip_next {
+            skip_next = false;
+            continue;
+        }
+
+        match b {
+            b'<' if !in_garbage => {
+                in_garbage = true;
+                s.garbage_start = Some(offset + i);
+            }
+            b'>' => {
+                in_garbage = false;
+                s.garbage_start = None;
+            }
+            b'!' => skip_next = true,
+            b'{' if !in_garbage => s.opens.push(offset + i),
+            b'}' if !in_garbage => {
+                if s.opens.pop().is_some() {
+                    s.local_score += s.opens.len() as u64 + 1;
+                    *s.closed_after.last_mut().expect("always an entry") += 1;
+                } else {
+                    s.closes.push(offset + i);
+                    s.closed_after.push(0);
+                }
+            }
+            _ if in_garbage => s.noncancelled += 1,
+            _ => {}
+        }
+    }
+
+    s.exit = Entry { in_garbage, skip: skip_next };
+    s
+}
+
+/// The state of the whole stream up to the end of the last chunk we applied
+struct Combined {
+    score: u64,
+    noncancelled: u64,
+    open_groups: Vec<usize>,
+    garbage_start: Option<usize>,
+    errors: Vec<StreamError>,
+}
+
+impl Combined {
+    fn apply(&mut self, s: &Summary) {
+        // Every group closed inside the chunk is deeper by however many of the
+        // groups from before it are still open.
+        let depth = self.open_groups.len() as u64;
+        self.score += s.local_score;
+        for (j, &n) in s.closed_after.iter().enumerate() {
+            self.score += n * depth.saturating_sub(j as u64);
+        }
+        for &offset in &s.closes {
+            if self.open_groups.pop().is_some() {
+                self.score += self.open_groups.len() as u64 + 1;
+            } else {
+                self.errors.push(StreamError::UnmatchedClose(offset));
+            }
+        }
+        self.open_groups.extend(&s.opens);
+
+        self.noncancelled += s.noncancelled;
+        self.garbage_start = if s.exit.in_garbage {
+            s.garbage_start.or(self.garbage_start)
+        } else {
+            None
+        };
+    }
+}
+
+/// Score `input` using up to `threads` threads. The result is the same as
+/// going through it in one go.
+pub fn scan(input: &[u8], threads: usize) -> Scan {
+    let size = input.len().div_ceil(threads.max(1)).max(1);
+    let chunks = input.chunks(size).collect::<Vec<_>>();
+
+    let summaries = std::thread::scope(|scope| {
+        let handles = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                scope.spawn(move || {
+                    let outside = summarise(chunk, i * size, Entry { in_garbage: false, skip: false });
+                    let inside = summarise(chunk, i * size, Entry { in_garbage: true, skip: false });
+                    (outside, inside)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|h| h.join().expect("summary thread")).collect::<Vec<_>>()
+    });
+
+    let mut c = Combined {
+        score: 0,
+        noncancelled: 0,
+        open_groups: Vec::new(),
+        garbage_start: None,
+        errors: Vec::new(),
+    };
+    let mut state = Entry { in_garbage: false, skip: false };
+    for (i, (outside, inside)) in summaries.into_iter().enumerate() {
+        let s = if state == outside.entry {
+            outside
+        } else if state == inside.entry {
+            inside
+        } else {
+            summarise(chunks[i], i * size, state)
+        };
+        c.apply(&s);
+        state = s.exit;
+    }
+
+    if state.skip {
+        c.errors.push(StreamError::TrailingCancel(input.len() - 1));
+    }
+    if let Some(start) = c.garbage_start {
+        c.errors.push(StreamError::UnterminatedGarbage(start));
+    }
+    c.errors.extend(c.open_groups.into_iter().map(StreamError::UnclosedGroup));
+    c.errors.sort_by_key(|e| e.offset());
+
+    Scan {
+        score: c.score,
+        noncancelled: c.noncancelled,
+        errors: c.errors,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use aoc2017::rng::XorShift;
+
+    /// Check splitting the stream up between different numbers of threads
+    /// against going through it in one go
+    fn check<I: IntoIterator<Item = usize>>(s: &[u8], threads: I) {
+        let expected = super::super::scan(Cursor::new(s));
+        for threads in threads {
+            assert_eq!(expected, super::scan(s, threads), "{:?} on {} threads", String::from_utf8_lossy(s), threads);
+        }
+    }
+
+    #[test]
+    fn count_score_cases() {
+        for s in &["{}", "{{{}}}", "{{{},{},{{}}}}", "{<a>,<a>,<a>,<a>}", "{{<ab>},{<ab>},{<ab>},{<ab>}}",
+                   "{{<!!>},{<!!>},{<!!>},{<!!>}}", "{{<a!>},{<a!>},{<a!>},{<ab>}}",
+                   "<>", "<random characters>", "<<<<>", "<{!>}>", "<!!>", "<!!!!>>", r#"<{o"i!a,<{i<a>"#,
+                   "{}}{<a>}", "{{},{<ab!", ""] {
+            check(s.as_bytes(), 1..s.len() + 2);
+        }
+    }
+
+    #[test]
+    fn fuzzed() {
+        // Mostly the characters which matter, so there's plenty going on
+        const ALPHABET: &[u8] = b"{{{}}}<<>>!!,a";
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..300 {
+            let s = (0..rng.next(60))
+                .map(|_| ALPHABET[rng.next(ALPHABET.len() as u64) as usize])
+                .collect::<Vec<_>>();
+            check(&s, vec![1, 2, 3, 4, 7, s.len()]);
+        }
+    }
+
+    #[test]
+    fn input() {
+        let input = include_bytes!("../../../input9.txt");
+        let expected = super::super::scan(Cursor::new(&input[..]));
+        for threads in &[2, 3, 8, 64, 1000] {
+            assert_eq!(expected, super::scan(input, *threads));
+        }
+    }
+}
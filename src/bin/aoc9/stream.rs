@@ -0,0 +1,138 @@
+//! Score a stream as it comes in, a chunk at a time. Between the characters we
+//! care about we jump ahead instead of going through the state machine for
+//! every byte, which matters inside long runs of garbage.
+
+use super::{Scan, StreamError};
+
+/// Whether we need to look at a byte outside of garbage. Anything else there
+/// doesn't change the score.
+fn is_special(b: u8) -> bool {
+    matches!(b, b'{' | b'}' | b'<' | b'>' | b'!')
+}
+
+/// The state of going through a stream, which carries over between chunks
+#[derive(Debug, Default)]
+pub struct StreamParser {
+    score: u64,
+    noncancelled: u64,
+    /// Where each group we're in started
+    open_groups: Vec<usize>,
+    /// Where the garbage we're in started
+    in_garbage: Option<usize>,
+    /// The last chunk ended with a `!` so the next byte is cancelled
+    skip_next: bool,
+    errors: Vec<StreamError>,
+    /// How many bytes we've been fed so far
+    offset: usize,
+}
+
+impl StreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Go through the next part of the stream
+    pub fn feed(&mut self, chunk: &[u8]) {
+        let mut i = 0;
+        while i < chunk.len() {
+            if self.skip_next {
+                self.skip_next = false;
+                i += 1;
+                continue;
+            }
+
+            // Everything up to the next byte which matters is either garbage
+            // we count or something we don't care about.
+            let rest = &chunk[i..];
+            let found = if self.in_garbage.is_some() {
+                let n = rest.iter().position(|&b| b == b'!' || b == b'>').unwrap_or(rest.len());
+                self.noncancelled += n as u64;
+                n
+            } else {
+                rest.iter().position(|&b| is_special(b)).unwrap_or(rest.len())
+            };
+            i += found;
+            if i == chunk.len() {
+                break;
+            }
+
+            let at = self.offset + i;
+            match chunk[i] {
+                b'<' if self.in_garbage.is_none() => self.in_garbage = Some(at),
+                b'>' => self.in_garbage = None,
+                b'!' => self.skip_next = true,
+                b'{' if self.in_garbage.is_none() => self.open_groups.push(at),
+                b'}' if self.in_garbage.is_none() => {
+                    if self.open_groups.pop().is_some() {
+                        self.score += self.open_groups.len() as u64 + 1;
+                    } else {
+                        self.errors.push(StreamError::UnmatchedClose(at));
+                    }
+                }
+                _ => unreachable!("the scan stops only on special bytes"),
+            }
+            i += 1;
+        }
+        self.offset += chunk.len();
+    }
+
+    /// The end of the stream, reporting anything left open
+    pub fn finish(mut self) -> Scan {
+        if self.skip_next {
+            self.errors.push(StreamError::TrailingCancel(self.offset - 1));
+        }
+        if let Some(start) = self.in_garbage {
+            self.errors.push(StreamError::UnterminatedGarbage(start));
+        }
+        self.errors.extend(self.open_groups.into_iter().map(StreamError::UnclosedGroup));
+        self.errors.sort_by_key(|e| e.offset());
+
+        Scan {
+            score: self.score,
+            noncancelled: self.noncancelled,
+            errors: self.errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StreamParser;
+    use super::super::Scan;
+
+    fn parse_in(chunks: &[&[u8]]) -> Scan {
+        let mut p = StreamParser::new();
+        for c in chunks {
+            p.feed(c);
+        }
+        p.finish()
+    }
+
+    #[test]
+    fn any_split() {
+        for s in &["{{<!!>},{<a!>},{<!>>}}", "{{<a!>},{<a!>},{<a!>},{<ab>}}", r#"<{o"i!a,<{i<a>"#,
+                   "{}}{<a>}", "{{},{<ab!", "{!}}", "<<!!!>{}>"] {
+            let s = s.as_bytes();
+            let whole = parse_in(&[s]);
+            for i in 0..s.len() + 1 {
+                for j in i..s.len() + 1 {
+                    assert_eq!(whole, parse_in(&[&s[..i], &s[i..j], &s[j..]]), "{:?} split at {} and {}",
+                               String::from_utf8_lossy(s), i, j);
+                }
+            }
+
+            let bytes = s.chunks(1).collect::<Vec<_>>();
+            assert_eq!(whole, parse_in(&bytes));
+        }
+    }
+
+    #[test]
+    fn input() {
+        let input = include_bytes!("../../../input9.txt");
+        let whole = parse_in(&[input]);
+        assert_eq!((21037, 9495), (whole.score, whole.noncancelled));
+        for size in &[1, 7, 4096] {
+            assert_eq!(whole, parse_in(&input.chunks(*size).collect::<Vec<_>>()));
+        }
+    }
+}
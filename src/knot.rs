@@ -0,0 +1,291 @@
+//! The knot hash from Day 10, which Day 14 builds on.
+//!
+//! Every round goes over the whole input again, so the hash can't be worked
+//! out as the bytes come in. `KnotHash` keeps them until it's asked for the
+//! result.
+
+use std::fmt::Write;
+use std::hash::Hasher;
+
+/// The lengths added to the end of the input
+pub const SUFFIX: [u8; 5] = [17, 31, 73, 47, 23];
+/// How many times the lengths are applied to the list
+pub const ROUNDS: usize = 64;
+/// How many numbers are in the list
+pub const SIZE: usize = 256;
+
+/// Reverse `len` elements of the circular list starting at `pos`
+pub fn reverse_range<T>(v: &mut [T], pos: usize, len: usize) {
+    let n = v.len();
+    for i in 0..len / 2 {
+        v.swap((pos + i) % n, (pos + len - 1 - i) % n);
+    }
+}
+
+/// Write the bytes out as lowercase hexadecimal
+pub fn to_hex(v: &[u8]) -> String {
+    let mut hex = String::new();
+    for n in v {
+        write!(&mut hex, "{:02x}", n).expect("writing hex");
+    }
+
+    hex
+}
+
+/// Why a set of parameters can't be used to hash anything
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    /// The list has to have at least one number, and they have to fit in a
+    /// `u16`
+    Size(usize),
+    /// The list has to split evenly into blocks for the dense hash
+    Block { size: usize, block: usize },
+    /// A length can't be longer than the list it reverses
+    Length { length: usize, size: usize },
+    /// The checksum multiplies the first two numbers, so there have to be two
+    Checksum(usize),
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParamError::Size(size) => write!(f, "a list of {} numbers is not between 1 and 65536", size),
+            ParamError::Block { size, block } => {
+                write!(f, "a list of {} numbers doesn't split into blocks of {}", size, block)
+            }
+            ParamError::Length { length, size } => {
+                write!(f, "length {} is longer than the list of {} numbers", length, size)
+            }
+            ParamError::Checksum(size) => {
+                write!(f, "a list of {} numbers is too short to multiply the first two", size)
+            }
+        }
+    }
+}
+
+/// Everything about the hash the puzzle fixes, so we can try others
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnotParams {
+    /// How many numbers are in the list
+    pub size: usize,
+    /// How many times the lengths are applied to the list
+    pub rounds: usize,
+    /// The lengths added to the end of the input
+    pub suffix: Vec<usize>,
+    /// How many numbers of the sparse hash go into each one of the dense hash
+    pub block: usize,
+}
+
+impl Default for KnotParams {
+    /// The hash from the puzzle
+    fn default() -> Self {
+        KnotParams {
+            size: SIZE,
+            rounds: ROUNDS,
+            suffix: SUFFIX.iter().map(|&b| b as usize).collect(),
+            block: 16,
+        }
+    }
+}
+
+impl KnotParams {
+    /// Check the list and blocks make sense together
+    pub fn validate(&self) -> Result<(), ParamError> {
+        if self.size == 0 || self.size > u16::MAX as usize + 1 {
+            return Err(ParamError::Size(self.size));
+        }
+        if self.block == 0 || !self.size.is_multiple_of(self.block) {
+            return Err(ParamError::Block { size: self.size, block: self.block });
+        }
+        Ok(())
+    }
+
+    /// The list after all the rounds over the lengths and the suffix
+    pub fn sparse_hash(&self, lengths: &[usize]) -> Result<Vec<u16>, ParamError> {
+        self.validate()?;
+        let lengths = lengths.iter().chain(&self.suffix).cloned().collect::<Vec<_>>();
+        if let Some(&length) = lengths.iter().find(|&&l| l > self.size) {
+            return Err(ParamError::Length { length, size: self.size });
+        }
+
+        let mut list = (0..self.size).map(|i| i as u16).collect::<Vec<u16>>();
+        let mut pos = 0;
+        let mut skip = 0;
+        for _ in 0..self.rounds {
+            for &len in &lengths {
+                reverse_range(&mut list, pos, len);
+                pos = (pos + len + skip) % self.size;
+                skip += 1;
+            }
+        }
+        Ok(list)
+    }
+
+    /// XOR each block of the sparse hash together
+    pub fn dense_hash(&self, sparse: &[u16]) -> Vec<u16> {
+        sparse
+            .chunks(self.block)
+            .map(|chunk| chunk.iter().fold(0, |acc, &x| acc ^ x))
+            .collect()
+    }
+
+    /// The dense hash of the bytes of the input
+    pub fn hash(&self, bytes: &[u8]) -> Result<Vec<u16>, ParamError> {
+        let lengths = bytes.iter().map(|&b| b as usize).collect::<Vec<_>>();
+        Ok(self.dense_hash(&self.sparse_hash(&lengths)?))
+    }
+
+    /// Write a hash out in hexadecimal, with as many digits for each number as
+    /// the biggest one in the list needs
+    pub fn to_hex(&self, hash: &[u16]) -> String {
+        let width = if self.size <= 256 { 2 } else { 4 };
+        let mut hex = String::new();
+        for n in hash {
+            write!(&mut hex, "{:01$x}", n, width).expect("writing hex");
+        }
+
+        hex
+    }
+}
+
+/// Go round the list once, reversing a stretch of it for each length. This is
+/// all part 1 needs.
+pub fn single_round(lengths: &[usize], size: usize) -> Result<Vec<u16>, ParamError> {
+    let params = KnotParams {
+        size,
+        rounds: 1,
+        suffix: Vec::new(),
+        block: 1,
+    };
+    params.sparse_hash(lengths)
+}
+
+/// The first two numbers after a single round multiplied together, which is
+/// the answer to part 1
+pub fn checksum(lengths: &[usize], size: usize) -> Result<usize, ParamError> {
+    let list = single_round(lengths, size)?;
+    match list[..] {
+        [a, b, ..] => Ok(a as usize * b as usize),
+        _ => Err(ParamError::Checksum(size)),
+    }
+}
+
+/// The knot hash of some bytes, which is what part 2 asks for
+pub fn full_hash(bytes: &[u8]) -> [u8; 16] {
+    let dense = KnotParams::default().hash(bytes).expect("the puzzle's parameters are valid");
+    let mut out = [0u8; 16];
+    for (o, d) in out.iter_mut().zip(dense) {
+        *o = d as u8;
+    }
+    out
+}
+
+/// A knot hash of everything given to `update`
+#[derive(Debug, Clone, Default)]
+pub struct KnotHash {
+    input: Vec<u8>,
+}
+
+impl KnotHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add more bytes to the input
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+    }
+
+    /// The 16 bytes of the hash of the input so far
+    pub fn finalize(&self) -> [u8; 16] {
+        full_hash(&self.input)
+    }
+
+    /// The hash as it's written in the puzzle
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.finalize())
+    }
+}
+
+impl Hasher for KnotHash {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    /// The first eight bytes of the hash
+    fn finish(&self) -> u64 {
+        let h = self.finalize();
+        h[..8].iter().fold(0, |acc, &b| acc << 8 | b as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::Hasher;
+    use super::{KnotHash, KnotParams, ParamError};
+
+    fn hex(s: &str) -> String {
+        let mut h = KnotHash::new();
+        h.update(s.as_bytes());
+        h.to_hex()
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!("a2582a3a0e66e6e86e3812dcb672a272", hex(""));
+        assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", hex("AoC 2017"));
+        assert_eq!("3efbe78a8d82f29979031a4aa0b16a9d", hex("1,2,3"));
+        assert_eq!("63960835bcdc130f0b66d7ff4f6a5a8e", hex("1,2,4"));
+    }
+
+    #[test]
+    fn sample_round() {
+        assert_eq!(Ok(vec![3, 4, 2, 1, 0]), super::single_round(&[3, 4, 1, 5], 5));
+        assert_eq!(Ok(12), super::checksum(&[3, 4, 1, 5], 5));
+    }
+
+    #[test]
+    fn params() {
+        let p = KnotParams::default();
+        assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", p.to_hex(&p.hash(b"AoC 2017").unwrap()));
+
+        // A bigger list takes four digits for each number
+        let p = KnotParams { size: 1024, rounds: 3, suffix: vec![1000], block: 16 };
+        let hash = p.hash(b"AoC 2017").unwrap();
+        assert_eq!(64, hash.len());
+        assert_eq!(256, p.to_hex(&hash).len());
+        assert!(hash.iter().any(|&n| n > 255));
+
+        // Every number is still there after any number of rounds
+        let mut sparse = p.sparse_hash(&[1000, 3, 999, 1024]).unwrap();
+        sparse.sort();
+        assert_eq!((0..1024).collect::<Vec<u16>>(), sparse);
+    }
+
+    #[test]
+    fn invalid_params() {
+        let p = |size, block| KnotParams { size, rounds: 1, suffix: vec![], block };
+        assert_eq!(Err(ParamError::Size(0)), p(0, 1).validate());
+        assert_eq!(Err(ParamError::Size(65537)), p(65537, 1).validate());
+        assert_eq!(Ok(()), p(65536, 16).validate());
+        assert_eq!(Err(ParamError::Block { size: 100, block: 16 }), p(100, 16).validate());
+        assert_eq!(Err(ParamError::Block { size: 100, block: 0 }), p(100, 0).validate());
+        assert_eq!(Err(ParamError::Length { length: 300, size: 256 }),
+                   KnotParams { suffix: vec![300], ..KnotParams::default() }.hash(b""));
+        assert_eq!("length 6 is longer than the list of 5 numbers",
+                   super::single_round(&[3, 6], 5).unwrap_err().to_string());
+        assert_eq!(Err(ParamError::Checksum(1)), super::checksum(&[1], 1));
+    }
+
+    #[test]
+    fn incremental() {
+        let mut h = KnotHash::new();
+        h.update(b"AoC");
+        h.update(b" 2017");
+        assert_eq!(hex("AoC 2017"), h.to_hex());
+
+        let mut hasher = KnotHash::new();
+        hasher.write(b"AoC 2017");
+        assert_eq!(0x33ef_eb34_ea91_902b, hasher.finish());
+    }
+}
@@ -0,0 +1,4 @@
+//! Code which more than one day's puzzle needs
+
+pub mod knot;
+pub mod rng;
@@ -0,0 +1,14 @@
+//! A small xorshift generator for the tests and benchmarks which need random
+//! input. It's seeded by hand so the input is the same on every run.
+
+pub struct XorShift(pub u64);
+
+impl XorShift {
+    /// The next number, below `n`
+    pub fn next(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}